@@ -0,0 +1,49 @@
+//! Times the kioku binary generating batches of names, the figures quoted in the README.
+//! Run with `RAYON_NUM_THREADS=1 cargo bench` to measure a single core.
+
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Runs of each case, the median is reported
+const RUNS: usize = 5;
+
+fn median(args: &[&str]) -> Duration {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let status = Command::new(env!("CARGO_BIN_EXE_kioku"))
+                .args(args)
+                // Keep a configuration of the machine out of the measurements
+                .env(
+                    "KIOKU_CONFIG",
+                    std::env::temp_dir().join("kioku-bench.toml"),
+                )
+                .stdout(Stdio::null())
+                .status()
+                .expect("Failed to run kioku");
+            assert!(status.success(), "kioku {} failed", args.join(" "));
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("kioku-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Failed to create a directory for the metadata");
+    let output = dir.join("runs.jsonl");
+    let output = output.to_string_lossy();
+    let cases: [&[&str]; 4] = [
+        &["-n", "1000000"],
+        &["-n", "100000"],
+        &["-n", "100000", "-f", "json"],
+        &["-n", "100000", "-o", &output],
+    ];
+    for args in cases {
+        let time = median(args);
+        println!("kioku {:<40} {:>8.3}s", args.join(" "), time.as_secs_f64());
+        let _ = std::fs::remove_file(&*output);
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
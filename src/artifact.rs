@@ -0,0 +1,156 @@
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// An output file of a run
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Artifact {
+    /// Relative to the directory of the metadata file when the file lies below it
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    pub added: String,
+}
+
+/// Outcome of checking an artifact against its recorded checksum
+pub enum Check {
+    Unchanged,
+    Modified,
+    Missing,
+}
+
+/// Directory artifact paths of runs recorded in `metadata_file` are relative to
+pub fn base_dir(metadata_file: &Path) -> &Path {
+    match metadata_file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    let mut file = fs::File::open(path)
+        .with_context(|| format!("Failed to open {}", path.to_string_lossy()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let n = file
+            .read(&mut buffer)
+            .with_context(|| format!("Failed to read {}", path.to_string_lossy()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Files below `path`, or `path` itself if it is a file
+fn files(path: &Path, found: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    if path.is_dir() {
+        walk(path, found)
+    } else {
+        found.push(path.to_path_buf());
+        Ok(())
+    }
+}
+
+/// Files below the directory `dir`. Links to files are recorded with the contents they point to,
+/// links to directories and dangling links are skipped so that a link cannot make the walk loop.
+fn walk(dir: &Path, found: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.to_string_lossy()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let kind = fs::symlink_metadata(&entry)
+            .with_context(|| format!("Failed to read {}", entry.to_string_lossy()))?
+            .file_type();
+        if kind.is_dir() {
+            walk(&entry, found)?;
+        } else if kind.is_symlink() && !entry.is_file() {
+            log::warn!("Skipping link {}", entry.to_string_lossy());
+        } else {
+            found.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Checksum the files at `paths`, descending into directories. `base` is the directory of the
+/// metadata file the artifacts are recorded in.
+pub fn collect(paths: &[PathBuf], base: &Path) -> anyhow::Result<Vec<Artifact>> {
+    let base = crate::platform::canonicalize(base).unwrap_or_else(|_| base.to_path_buf());
+    let mut found = Vec::new();
+    for path in paths {
+        files(path, &mut found)?;
+    }
+    found
+        .iter()
+        .map(|file| {
+            let absolute = crate::platform::canonicalize(file)
+                .with_context(|| format!("No such file {}", file.to_string_lossy()))?;
+            let size = fs::metadata(&absolute)?.len();
+            let sha256 = sha256_file(&absolute)?;
+            let path = absolute.strip_prefix(&base).unwrap_or(&absolute);
+            Ok(Artifact {
+                path: crate::platform::portable(path),
+                size,
+                sha256,
+                added: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+            })
+        })
+        .collect()
+}
+
+/// Compare an artifact with the file it was recorded from
+pub fn check(artifact: &Artifact, base: &Path) -> anyhow::Result<Check> {
+    let path = base.join(&artifact.path);
+    if !path.is_file() {
+        return Ok(Check::Missing);
+    }
+    if fs::metadata(&path)?.len() != artifact.size || sha256_file(&path)? != artifact.sha256 {
+        return Ok(Check::Modified);
+    }
+    Ok(Check::Unchanged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_files_are_modified() {
+        let dir = std::env::temp_dir().join(format!("kioku-artifact-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("out")).unwrap();
+        fs::write(dir.join("out/model.bin"), "weights").unwrap();
+        fs::write(dir.join("out/log.txt"), "loss 0.1").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("..", dir.join("out/loop")).unwrap();
+        let artifacts = collect(&[dir.join("out")], &dir).unwrap();
+        let paths: Vec<_> = artifacts.iter().map(|a| a.path.as_str()).collect();
+        assert_eq!(paths, ["out/log.txt", "out/model.bin"]);
+        assert!(matches!(
+            check(&artifacts[1], &dir).unwrap(),
+            Check::Unchanged
+        ));
+        fs::write(dir.join("out/model.bin"), "weightz").unwrap();
+        assert!(matches!(
+            check(&artifacts[1], &dir).unwrap(),
+            Check::Modified
+        ));
+        fs::remove_file(dir.join("out/log.txt")).unwrap();
+        assert!(matches!(
+            check(&artifacts[0], &dir).unwrap(),
+            Check::Missing
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
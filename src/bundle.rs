@@ -0,0 +1,200 @@
+use crate::metadata::{self, MetaData};
+use anyhow::{Context, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Version of the bundle layout, bump when older versions can no longer read it
+const BUNDLE_VERSION: u64 = 1;
+const MANIFEST: &str = "manifest.json";
+const RUNS: &str = "runs.jsonl";
+const PATCHES: &str = "patches/";
+
+/// Contents of a bundle, written first so readers can check the version
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Manifest {
+    bundle_version: u64,
+    kioku_version: String,
+    created: String,
+    /// Labels of the runs in the bundle
+    runs: Vec<String>,
+    /// Files in the patches directory, one per commit the runs were started at
+    #[serde(default)]
+    patches: Vec<String>,
+}
+
+/// Runs read from a bundle, along with the patches of the commits they were started at
+pub struct Bundle {
+    pub records: Vec<MetaData>,
+    /// File names and contents of the patches
+    pub patches: Vec<(String, Vec<u8>)>,
+}
+
+/// The commit `revision` as a patch that `git am` applies
+fn commit_patch(repo: &git2::Repository, revision: &str) -> anyhow::Result<Vec<u8>> {
+    let commit = git2::Oid::from_str(revision)
+        .and_then(|oid| repo.find_commit(oid))
+        .with_context(|| format!("Commit {} is not in the repository", revision))?;
+    let email = git2::Email::from_commit(&commit, &mut git2::EmailCreateOptions::new())
+        .with_context(|| format!("Failed to create a patch of commit {}", revision))?;
+    Ok(email.as_slice().to_vec())
+}
+
+fn append(
+    builder: &mut tar::Builder<impl Write>,
+    path: &str,
+    data: &[u8],
+    mtime: u64,
+) -> anyhow::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    builder
+        .append_data(&mut header, path, data)
+        .with_context(|| format!("Failed to add {} to the bundle", path))
+}
+
+/// Write `records` to the gzip compressed tarball `out`. With `patches`, the commits the runs
+/// were started at are added as patches, read from the repository of the current directory.
+pub fn export(out: &Path, records: &[MetaData], patches: bool) -> anyhow::Result<()> {
+    let mut files = Vec::new();
+    if patches {
+        let repo = git2::Repository::discover(".")
+            .context("Exporting patches needs to be run inside a git repository")?;
+        for meta in records {
+            let Some(revision) = &meta.revision else {
+                log::warn!("{} has no revision to export a patch of", meta.label);
+                continue;
+            };
+            let name = format!("{}.patch", revision);
+            if !files.iter().any(|(file, _)| *file == name) {
+                files.push((name, commit_patch(&repo, revision)?));
+            }
+        }
+    }
+    // Seals of records exported from a sealed file would break the chain of the file they are
+    // imported into
+    let mut runs = String::new();
+    for meta in records {
+        let mut value = serde_json::to_value(meta)?;
+        if let Some(fields) = value.as_object_mut() {
+            crate::seal::strip(fields);
+        }
+        runs.push_str(&serde_json::to_string(&value)?);
+        runs.push('\n');
+    }
+    let now = chrono::Utc::now();
+    let manifest = Manifest {
+        bundle_version: BUNDLE_VERSION,
+        kioku_version: env!("CARGO_PKG_VERSION").to_string(),
+        created: now.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+        runs: records.iter().map(|meta| meta.label.clone()).collect(),
+        patches: files.iter().map(|(name, _)| name.clone()).collect(),
+    };
+    let file = fs::File::create(out)
+        .with_context(|| format!("Failed to create bundle {}", out.to_string_lossy()))?;
+    let mtime = now.timestamp().max(0) as u64;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    append(
+        &mut builder,
+        MANIFEST,
+        &serde_json::to_vec_pretty(&manifest)?,
+        mtime,
+    )?;
+    append(&mut builder, RUNS, runs.as_bytes(), mtime)?;
+    for (name, patch) in &files {
+        append(&mut builder, &format!("{}{}", PATCHES, name), patch, mtime)?;
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Read the runs and patches of a bundle written by `export`, upgrading records of older versions
+pub fn read(path: &Path) -> anyhow::Result<Bundle> {
+    let file = fs::File::open(path)
+        .with_context(|| format!("Failed to open bundle {}", path.to_string_lossy()))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut manifest = None;
+    let mut records = None;
+    let mut patches = Vec::new();
+    let context = || format!("Failed to read bundle {}", path.to_string_lossy());
+    for entry in archive.entries().with_context(context)? {
+        let mut entry = entry.with_context(context)?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .with_context(|| format!("Failed to read {} from the bundle", name))?;
+        if name == MANIFEST {
+            let found: Manifest =
+                serde_json::from_slice(&data).context("Invalid manifest in bundle")?;
+            if found.bundle_version > BUNDLE_VERSION {
+                bail!(
+                    "The bundle was written by kioku {}, which is newer than this version",
+                    found.kioku_version
+                );
+            }
+            manifest = Some(found);
+        } else if name == RUNS {
+            let mut found = Vec::new();
+            for value in serde_json::Deserializer::from_slice(&data).into_iter() {
+                let mut value = value.context("Invalid record in bundle")?;
+                metadata::migrate_record(&mut value)?;
+                // Bundles of older versions kept the seals of records exported from sealed files
+                if let Some(fields) = value.as_object_mut() {
+                    crate::seal::strip(fields);
+                }
+                found.push(serde_json::from_value(value).context("Invalid record in bundle")?);
+            }
+            records = Some(found);
+        } else if let Some(patch) = name.strip_prefix(PATCHES) {
+            // Patch names end up in paths, so nothing may lead out of the target directory
+            if !crate::platform::valid_file_name(patch) {
+                bail!("Invalid patch name {} in bundle", patch);
+            }
+            patches.push((patch.to_string(), data));
+        }
+    }
+    if manifest.is_none() {
+        bail!("{} is not a kioku bundle", path.to_string_lossy());
+    }
+    Ok(Bundle {
+        records: records.context("The bundle contains no runs")?,
+        patches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundles_round_trip() {
+        let path = std::env::temp_dir().join(format!("kioku-test-{}.tar.gz", std::process::id()));
+        let mut records = metadata::collect(
+            &["gene-ruin-note".to_string(), "robe-speed-fake".to_string()],
+            &[],
+            &metadata::Clock::default(),
+        );
+        records[0].add_note("learning rate was too high");
+        records[1].extra.insert(
+            crate::seal::FIELD.to_string(),
+            serde_json::json!({"seq": 0}),
+        );
+        export(&path, &records, false).unwrap();
+        let bundle = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(bundle.records.len(), 2);
+        assert_eq!(bundle.records[0].run_id, records[0].run_id);
+        assert_eq!(
+            bundle.records[0].notes[0].text,
+            "learning rate was too high"
+        );
+        assert!(bundle.patches.is_empty());
+        assert!(!bundle.records[1].extra.contains_key(crate::seal::FIELD));
+    }
+}
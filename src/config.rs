@@ -0,0 +1,324 @@
+use crate::error::KiokuErr;
+use crate::git::GitRecord;
+use crate::hook::OnFailure;
+use crate::metadata::{Format, Zone};
+use crate::name::{Case, Charset, Generator, Prefix, Suffix};
+use crate::scheduler::Scheduler;
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Defaults loaded from the configuration file, command line flags take precedence
+#[derive(serde::Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub length: Option<usize>,
+    pub style: Option<Generator>,
+    pub syllables: Option<usize>,
+    pub wordlist: Option<String>,
+    pub output: Option<String>,
+    pub format: Option<Format>,
+    pub template: Option<String>,
+    pub prefix: Option<Prefix>,
+    pub namespace: Option<String>,
+    pub separator: Option<String>,
+    pub case: Option<Case>,
+    pub suffix: Option<Suffix>,
+    pub exclude_file: Option<String>,
+    pub profanity_filter: Option<bool>,
+    pub max_len: Option<usize>,
+    pub charset: Option<Charset>,
+    pub candidates: Option<usize>,
+    pub mkdir: Option<bool>,
+    pub experiments_dir: Option<String>,
+    pub skeleton: Option<String>,
+    pub latest: Option<bool>,
+    pub wordlist_url: Option<String>,
+    pub wordlist_sha256: Option<String>,
+    pub wordlist_token: Option<String>,
+    pub wordlist_user: Option<String>,
+    pub wordlist_password: Option<String>,
+    pub proxy: Option<String>,
+    pub notify_url: Option<String>,
+    pub notify_headers: Option<BTreeMap<String, String>>,
+    pub git_record: Option<GitRecord>,
+    pub repositories: Option<Vec<String>>,
+    pub remote_user: Option<String>,
+    pub remote_password: Option<String>,
+    pub commit_url: Option<String>,
+    pub scheduler: Option<Scheduler>,
+    pub capture_container: Option<bool>,
+    pub filter_cmd: Option<String>,
+    pub lang: Option<String>,
+    pub timestamp: Option<Zone>,
+    pub time_format: Option<String>,
+    pub pre_generate: Option<String>,
+    pub post_generate: Option<String>,
+    pub hook_failure: Option<OnFailure>,
+    pub sealed: Option<bool>,
+    pub seal_key: Option<String>,
+}
+
+impl Config {
+    /// Combine two configurations, values set in `other` take precedence
+    fn merge(self, other: Config) -> Config {
+        Config {
+            length: other.length.or(self.length),
+            style: other.style.or(self.style),
+            syllables: other.syllables.or(self.syllables),
+            wordlist: other.wordlist.or(self.wordlist),
+            output: other.output.or(self.output),
+            format: other.format.or(self.format),
+            template: other.template.or(self.template),
+            prefix: other.prefix.or(self.prefix),
+            namespace: other.namespace.or(self.namespace),
+            separator: other.separator.or(self.separator),
+            case: other.case.or(self.case),
+            suffix: other.suffix.or(self.suffix),
+            exclude_file: other.exclude_file.or(self.exclude_file),
+            profanity_filter: other.profanity_filter.or(self.profanity_filter),
+            max_len: other.max_len.or(self.max_len),
+            charset: other.charset.or(self.charset),
+            candidates: other.candidates.or(self.candidates),
+            mkdir: other.mkdir.or(self.mkdir),
+            experiments_dir: other.experiments_dir.or(self.experiments_dir),
+            skeleton: other.skeleton.or(self.skeleton),
+            latest: other.latest.or(self.latest),
+            wordlist_url: other.wordlist_url.or(self.wordlist_url),
+            wordlist_sha256: other.wordlist_sha256.or(self.wordlist_sha256),
+            wordlist_token: other.wordlist_token.or(self.wordlist_token),
+            wordlist_user: other.wordlist_user.or(self.wordlist_user),
+            wordlist_password: other.wordlist_password.or(self.wordlist_password),
+            proxy: other.proxy.or(self.proxy),
+            notify_url: other.notify_url.or(self.notify_url),
+            notify_headers: other.notify_headers.or(self.notify_headers),
+            git_record: other.git_record.or(self.git_record),
+            repositories: other.repositories.or(self.repositories),
+            remote_user: other.remote_user.or(self.remote_user),
+            remote_password: other.remote_password.or(self.remote_password),
+            commit_url: other.commit_url.or(self.commit_url),
+            scheduler: other.scheduler.or(self.scheduler),
+            capture_container: other.capture_container.or(self.capture_container),
+            filter_cmd: other.filter_cmd.or(self.filter_cmd),
+            lang: other.lang.or(self.lang),
+            timestamp: other.timestamp.or(self.timestamp),
+            time_format: other.time_format.or(self.time_format),
+            pre_generate: other.pre_generate.or(self.pre_generate),
+            post_generate: other.post_generate.or(self.post_generate),
+            hook_failure: other.hook_failure.or(self.hook_failure),
+            sealed: other.sealed.or(self.sealed),
+            seal_key: other.seal_key.or(self.seal_key),
+        }
+    }
+}
+
+/// Name of the per-project configuration file
+pub static PROJECT_CONFIG: &str = ".kioku.toml";
+
+/// Directory for data kioku keeps between invocations
+pub fn data_dir() -> anyhow::Result<PathBuf> {
+    directories::ProjectDirs::from("", "", "kioku")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .ok_or(KiokuErr::NoDataDir.into())
+}
+
+/// Location of the configuration file, `KIOKU_CONFIG` overrides the platform default
+pub fn config_path() -> Option<PathBuf> {
+    env::var_os("KIOKU_CONFIG").map(PathBuf::from).or_else(|| {
+        directories::ProjectDirs::from("", "", "kioku")
+            .map(|dirs| dirs.config_dir().join("config.toml"))
+    })
+}
+
+/// Location of the project configuration, either in the current directory or the repository root
+pub fn project_config_path() -> Option<PathBuf> {
+    let local = PathBuf::from(PROJECT_CONFIG);
+    if local.is_file() {
+        return Some(local);
+    }
+    git2::Repository::discover(".")
+        .ok()
+        .and_then(|rep| rep.workdir().map(|dir| dir.join(PROJECT_CONFIG)))
+        .filter(|path| path.is_file())
+}
+
+fn read_config(path: &Path) -> anyhow::Result<Config> {
+    log::debug!("Loading configuration from {}", path.to_string_lossy());
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.to_string_lossy()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse config file {}", path.to_string_lossy()))
+}
+
+/// Relative paths in a project configuration are relative to the project, not the working directory.
+/// Stdout (`-`) and URLs such as `ssh://host/runs.jsonl` are not paths and are left as they are,
+/// except for the path of a `sqlite://` database.
+fn rebase(path: String, base: &Path) -> String {
+    if let Some(db) = path.strip_prefix("sqlite://") {
+        format!("sqlite://{}", rebase(db.to_string(), base))
+    } else if path == crate::sink::STDOUT || path.contains("://") || Path::new(&path).is_absolute()
+    {
+        path
+    } else {
+        base.join(path).to_string_lossy().into_owned()
+    }
+}
+
+/// Keys only the global configuration can set, since a project configuration comes with whatever
+/// repository kioku runs in and these run commands or hold credentials
+fn global_only(project: &Config) -> Option<&'static str> {
+    [
+        ("pre_generate", project.pre_generate.is_some()),
+        ("post_generate", project.post_generate.is_some()),
+        ("filter_cmd", project.filter_cmd.is_some()),
+        ("remote_user", project.remote_user.is_some()),
+        ("remote_password", project.remote_password.is_some()),
+        ("notify_url", project.notify_url.is_some()),
+        ("notify_headers", project.notify_headers.is_some()),
+        ("seal_key", project.seal_key.is_some()),
+        ("wordlist_url", project.wordlist_url.is_some()),
+        ("wordlist_token", project.wordlist_token.is_some()),
+        ("wordlist_user", project.wordlist_user.is_some()),
+        ("wordlist_password", project.wordlist_password.is_some()),
+        ("proxy", project.proxy.is_some()),
+    ]
+    .into_iter()
+    .find_map(|(key, set)| set.then_some(key))
+}
+
+/// Scheme and authority of an `http://` or `https://` URL
+fn http_origin(url: &str) -> Option<&str> {
+    let scheme = ["https://", "http://"]
+        .into_iter()
+        .find(|scheme| url.starts_with(scheme))?;
+    let end = url[scheme.len()..]
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |i| scheme.len() + i);
+    Some(&url[..end])
+}
+
+/// Whether the global `remote_user` and `remote_password` may be sent to the output of the
+/// project, which is only the case for the server the global output names
+fn sends_credentials(global: &Config, project: &Config) -> bool {
+    match project.output.as_deref().and_then(http_origin) {
+        Some(origin) => global.output.as_deref().and_then(http_origin) == Some(origin),
+        None => true,
+    }
+}
+
+/// Load the global configuration, overridden by the project configuration if there is one
+pub fn load() -> anyhow::Result<Config> {
+    let mut global = match config_path() {
+        Some(path) if path.is_file() => read_config(&path)?,
+        _ => Config::default(),
+    };
+    let Some(path) = project_config_path() else {
+        return Ok(global);
+    };
+    let mut project = read_config(&path)?;
+    if let Some(key) = global_only(&project) {
+        anyhow::bail!(
+            "{} sets {}, which only the global configuration can set",
+            path.to_string_lossy(),
+            key
+        );
+    }
+    if global.remote_user.is_some() && !sends_credentials(&global, &project) {
+        log::warn!(
+            "Not sending remote_user to {}, which only the project configuration names",
+            project.output.as_deref().unwrap_or_default()
+        );
+        global.remote_user = None;
+        global.remote_password = None;
+    }
+    let base = path.parent().unwrap_or(Path::new("."));
+    project.output = project.output.map(|output| rebase(output, base));
+    project.experiments_dir = project.experiments_dir.map(|dir| rebase(dir, base));
+    project.skeleton = project.skeleton.map(|dir| rebase(dir, base));
+    project.exclude_file = project.exclude_file.map(|file| rebase(file, base));
+    project.repositories = project.repositories.map(|repositories| {
+        repositories
+            .into_iter()
+            .map(|repo| rebase(repo, base))
+            .collect()
+    });
+    project.wordlist = project.wordlist.map(|words| {
+        if crate::wordlist::is_installed(&words) {
+            words
+        } else {
+            rebase(words, base)
+        }
+    });
+    Ok(global.merge(project))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_paths_are_rebased() {
+        let base = Path::new("/tmp/kr");
+        assert_eq!(
+            rebase("runs.jsonl".to_string(), base),
+            Path::new("/tmp/kr/runs.jsonl").to_string_lossy()
+        );
+        assert_eq!(
+            rebase("/var/runs.jsonl".to_string(), base),
+            "/var/runs.jsonl"
+        );
+        assert_eq!(rebase("-".to_string(), base), "-");
+        for url in [
+            "ssh://host/runs.jsonl",
+            "https://example.com/runs",
+            "http://example.com/runs",
+            "sqlite:///var/runs.db",
+        ] {
+            assert_eq!(rebase(url.to_string(), base), url);
+        }
+        assert_eq!(
+            rebase("sqlite://runs.db".to_string(), base),
+            format!(
+                "sqlite://{}",
+                Path::new("/tmp/kr/runs.db").to_string_lossy()
+            )
+        );
+    }
+
+    #[test]
+    fn credentials_stay_with_the_global_server() {
+        let config = |toml: &str| toml::from_str::<Config>(toml).unwrap();
+        let global = config("output = \"https://runs.example.com/log\"\nremote_user = \"alice\"");
+        assert!(sends_credentials(&global, &config("length = 3")));
+        assert!(sends_credentials(
+            &global,
+            &config("output = \"https://runs.example.com/other\"")
+        ));
+        for output in [
+            "http://127.0.0.1:18765/collect",
+            "http://runs.example.com/log",
+            "https://runs.example.com.evil.net/log",
+        ] {
+            let project = config(&format!("output = \"{}\"", output));
+            assert!(!sends_credentials(&global, &project), "{}", output);
+        }
+        assert!(!sends_credentials(
+            &config("remote_user = \"alice\""),
+            &config("output = \"https://runs.example.com/log\"")
+        ));
+    }
+
+    #[test]
+    fn projects_cannot_run_commands() {
+        let project: Config = toml::from_str("pre_generate = \"touch PWNED\"").unwrap();
+        assert_eq!(global_only(&project), Some("pre_generate"));
+        let project: Config = toml::from_str("filter_cmd = \"sh evil.sh\"").unwrap();
+        assert_eq!(global_only(&project), Some("filter_cmd"));
+        let project: Config = toml::from_str("wordlist_url = \"https://evil.net/w\"").unwrap();
+        assert_eq!(global_only(&project), Some("wordlist_url"));
+        let project: Config = toml::from_str("length = 3\noutput = \"runs.jsonl\"").unwrap();
+        assert_eq!(global_only(&project), None);
+    }
+}
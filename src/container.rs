@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The container image a run was started in
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Container {
+    /// docker, podman or another container engine
+    pub runtime: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Image reference the container was created from, e.g. `ghcr.io/org/train:1.2`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Local image id (`sha256:...`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_id: Option<String>,
+    /// Registry digest identifying the image across machines, e.g. `ghcr.io/org/train@sha256:...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_digest: Option<String>,
+    /// OCI annotation labels of the image (org.opencontainers.image.*)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+fn is_container_id(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Find a 64 digit container id in cgroup or mount paths such as `/docker/<id>`,
+/// `docker-<id>.scope`, `libpod-<id>` or `/containers/<id>/hostname`
+fn id_in_paths(contents: &str) -> Option<String> {
+    contents
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .map(|part| {
+            let part = part.strip_suffix(".scope").unwrap_or(part);
+            part.rsplit('-').next().unwrap_or(part)
+        })
+        .find(|part| is_container_id(part))
+        .map(str::to_string)
+}
+
+/// Podman describes the container in `/run/.containerenv` as `key="value"` lines
+fn podman_containerenv() -> Option<Container> {
+    let contents = fs::read_to_string("/run/.containerenv").ok()?;
+    let mut container = Container {
+        runtime: "podman".to_string(),
+        ..Default::default()
+    };
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = Some(value.trim_matches('"').to_string()).filter(|v| !v.is_empty());
+        match key {
+            "id" => container.id = value,
+            "image" => container.image = value,
+            "imageid" => container.image_id = value.map(|id| format!("sha256:{}", id)),
+            _ => {}
+        }
+    }
+    Some(container)
+}
+
+fn detect() -> Option<Container> {
+    if let Some(container) = podman_containerenv() {
+        return Some(container);
+    }
+    let cgroup = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    let id = id_in_paths(&cgroup).or_else(|| id_in_paths(&mountinfo));
+    let runtime = if Path::new("/.dockerenv").exists() || cgroup.contains("docker") {
+        "docker".to_string()
+    } else if let Ok(runtime) = std::env::var("container") {
+        // Set by podman, systemd-nspawn and others
+        runtime
+    } else if id.is_some() {
+        "unknown".to_string()
+    } else {
+        return None;
+    };
+    Some(Container {
+        runtime,
+        id,
+        ..Default::default()
+    })
+}
+
+/// GET `path` from the Docker compatible API on the unix socket `socket`
+#[cfg(unix)]
+fn engine_get(socket: &Path, path: &str) -> anyhow::Result<serde_json::Value> {
+    use std::io::{Read, Write};
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+    // HTTP/1.0 keeps the response free of chunked encoding
+    write!(stream, "GET {} HTTP/1.0\r\nHost: localhost\r\n\r\n", path)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow::anyhow!("Malformed response"))?;
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    if status != "200" {
+        anyhow::bail!("{} returned {}", path, status);
+    }
+    Ok(serde_json::from_str(body)?)
+}
+
+/// Sockets of the Docker compatible APIs of Docker and Podman that may be mounted into the
+/// container
+#[cfg(unix)]
+fn engine_sockets() -> Vec<std::path::PathBuf> {
+    let mut sockets = Vec::new();
+    if let Ok(host) = std::env::var("DOCKER_HOST")
+        && let Some(path) = host.strip_prefix("unix://")
+    {
+        sockets.push(path.into());
+    }
+    sockets.push("/var/run/docker.sock".into());
+    sockets.push("/run/podman/podman.sock".into());
+    if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
+        sockets.push(Path::new(&runtime_dir).join("podman/podman.sock"));
+    }
+    sockets.retain(|socket| socket.exists());
+    sockets
+}
+
+/// Fill in the image of the container from the container engine, if its socket is reachable
+#[cfg(unix)]
+fn inspect(container: &mut Container) {
+    let Some(id) = container.id.clone() else {
+        return;
+    };
+    for socket in engine_sockets() {
+        let inspected = engine_get(&socket, &format!("/containers/{}/json", id)).and_then(|info| {
+            let image_id = info["Image"].as_str().unwrap_or_default().to_string();
+            let image = engine_get(&socket, &format!("/images/{}/json", image_id))?;
+            Ok((info, image_id, image))
+        });
+        match inspected {
+            Ok((info, image_id, image)) => {
+                if let Some(name) = info["Config"]["Image"].as_str() {
+                    container.image = Some(name.to_string());
+                }
+                container.image_id = Some(image_id);
+                container.image_digest = image["RepoDigests"][0].as_str().map(str::to_string);
+                if let Some(labels) = image["Config"]["Labels"].as_object() {
+                    container.labels = labels
+                        .iter()
+                        .filter(|(key, _)| key.starts_with("org.opencontainers.image."))
+                        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                        .collect();
+                }
+                return;
+            }
+            Err(e) => log::debug!(
+                "Failed to inspect container via {}: {:#}",
+                socket.to_string_lossy(),
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn inspect(_container: &mut Container) {}
+
+/// The container the current process runs in, if any
+pub fn current_container() -> Option<Container> {
+    let Some(mut container) = detect() else {
+        log::warn!("--capture-container given, but not running in a known container");
+        return None;
+    };
+    inspect(&mut container);
+    if container.image.is_none() {
+        log::warn!(
+            "Could not determine the image of the container, mount the container engine socket to record it"
+        );
+    }
+    Some(container)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_ids_in_cgroup_paths() {
+        let id = "3f4e8a1b".repeat(8);
+        for contents in [
+            format!("0::/docker/{}\n", id),
+            format!("0::/system.slice/docker-{}.scope\n", id),
+            format!("0::/machine.slice/libpod-{}.scope/container\n", id),
+            format!(
+                "612 600 0:52 /var/lib/docker/containers/{}/hostname /etc/hostname rw\n",
+                id
+            ),
+        ] {
+            assert_eq!(id_in_paths(&contents).as_deref(), Some(id.as_str()));
+        }
+        assert_eq!(id_in_paths("0::/\n4:memory:/user.slice\n"), None);
+    }
+}
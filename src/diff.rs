@@ -0,0 +1,144 @@
+use crate::metadata::MetaData;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+/// Fields that differ between any two runs, only compared with `--all`
+const RUN_SPECIFIC: &[&str] = &[
+    "schema_version",
+    "label",
+    "run_id",
+    "aliases",
+    "prefix",
+    "timestamp",
+    "finished",
+    "directory",
+    "stdout",
+    "stderr",
+    "notes",
+    "events",
+    "artifacts",
+    "mlflow",
+    "wandb",
+    "cloned_from",
+    "seal",
+];
+
+/// A field whose value differs between two runs, `None` where the run lacks the field
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct Change {
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub a: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b: Option<Value>,
+}
+
+/// Flatten nested objects into dotted keys, so `revisions` compare per repository
+fn flatten(prefix: &str, value: &Value, fields: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, fields);
+            }
+        }
+        Value::Null => {}
+        _ => {
+            fields.insert(prefix.to_string(), value.clone());
+        }
+    }
+}
+
+fn fields(meta: &MetaData, all: bool) -> anyhow::Result<BTreeMap<String, Value>> {
+    let mut fields = BTreeMap::new();
+    flatten("", &serde_json::to_value(meta)?, &mut fields);
+    if !all {
+        fields.retain(|key, _| {
+            let top = key.split('.').next().unwrap_or(key);
+            !RUN_SPECIFIC.contains(&top)
+        });
+    }
+    Ok(fields)
+}
+
+/// Fields that differ between two runs, ordered by key
+pub fn diff(a: &MetaData, b: &MetaData, all: bool) -> anyhow::Result<Vec<Change>> {
+    let mut a = fields(a, all)?;
+    let mut b = fields(b, all)?;
+    let keys: BTreeSet<String> = a.keys().chain(b.keys()).cloned().collect();
+    let mut changes = Vec::new();
+    for key in keys {
+        let (a, b) = (a.remove(&key), b.remove(&key));
+        if a != b {
+            changes.push(Change { key, a, b });
+        }
+    }
+    Ok(changes)
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Print changes as `~ key: a -> b`, `- key: a` for fields only the first run has and
+/// `+ key: b` for fields only the second run has
+pub fn print(writer: &mut impl Write, changes: &[Change]) -> io::Result<()> {
+    for change in changes {
+        match (&change.a, &change.b) {
+            (Some(a), Some(b)) => {
+                writeln!(writer, "~ {}: {} -> {}", change.key, display(a), display(b))?
+            }
+            (Some(a), None) => writeln!(writer, "- {}: {}", change.key, display(a))?,
+            (None, Some(b)) => writeln!(writer, "+ {}: {}", change.key, display(b))?,
+            (None, None) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(label: &str, extra: Value) -> MetaData {
+        let mut record = serde_json::json!({
+            "schema_version": 1,
+            "label": label,
+            "revision": "abc",
+            "timestamp": format!("2026-01-0{}T10:00:00+00:00", label.len()),
+        });
+        record
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(record).unwrap()
+    }
+
+    #[test]
+    fn changed_added_and_removed_fields() {
+        let a = run(
+            "a",
+            serde_json::json!({"lr": 0.1, "batch": 32, "revisions": {"lib": "1"}}),
+        );
+        let b = run(
+            "bb",
+            serde_json::json!({"lr": 0.2, "seed": 7, "revisions": {"lib": "1"}}),
+        );
+        let changes = diff(&a, &b, false).unwrap();
+        let keys: Vec<&str> = changes.iter().map(|c| c.key.as_str()).collect();
+        assert_eq!(keys, ["batch", "lr", "seed"]);
+        assert_eq!(changes[0].b, None);
+        assert_eq!(changes[2].a, None);
+        let all = diff(&a, &b, true).unwrap();
+        assert!(all.iter().any(|c| c.key == "timestamp"));
+        assert!(all.iter().any(|c| c.key == "label"));
+    }
+}
@@ -0,0 +1,406 @@
+use std::io;
+
+/// Errors of kioku. Helpers return `anyhow::Result`, with these errors at the root of the chain
+/// where the category of a failure matters to the caller, e.g. for the exit code.
+#[derive(Debug, thiserror::Error)]
+pub enum KiokuErr {
+    /// Exit with this code without a message, as `kioku run` does with the code of the command
+    #[error("")]
+    Exit(i32),
+    #[error("Aborted: {0}")]
+    Declined(String),
+    #[error(transparent)]
+    Wordlist(#[from] WordlistErr),
+    #[error("Failed to write metadata file {path}")]
+    MetadataWrite {
+        path: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error(
+        "Could not generate {count} distinct names meeting the constraints, the wordlist is too small or the constraints too strict{}",
+        if *.filtered { " (or the filter command rejects too many names)" } else { "" }
+    )]
+    NamesExhausted { count: usize, filtered: bool },
+    #[error("The {hook} hook {command} failed with {status}")]
+    HookFailed {
+        hook: &'static str,
+        command: String,
+        status: std::process::ExitStatus,
+    },
+    #[error("Line {line} breaks the seal: {reason}")]
+    SealBroken { line: usize, reason: String },
+    /// Displayed with its whole chain of causes
+    #[error("{0:#}")]
+    ApplicationErr(anyhow::Error),
+    #[error("Failed to determine the kioku data directory")]
+    NoDataDir,
+    /// Failures of the libraries kioku calls into, with what kioku was doing if known
+    #[error("{}", .context.as_deref().unwrap_or("I/O error"))]
+    Io {
+        context: Option<String>,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{}", .context.as_deref().unwrap_or("Git error"))]
+    Git {
+        context: Option<String>,
+        #[source]
+        source: git2::Error,
+    },
+    #[error("{}", .context.as_deref().unwrap_or("HTTP request failed"))]
+    Http {
+        context: Option<String>,
+        #[source]
+        source: ureq::Error,
+    },
+    #[error("{}", .context.as_deref().unwrap_or("Serialization error"))]
+    Serialization {
+        context: Option<String>,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl From<anyhow::Error> for KiokuErr {
+    fn from(value: anyhow::Error) -> Self {
+        KiokuErr::ApplicationErr(value)
+    }
+}
+
+/// Errors of the libraries kioku calls into, which have a variant of `KiokuErr` with optional context
+pub trait Source: Sized {
+    fn with_context(self, context: Option<String>) -> KiokuErr;
+}
+
+impl Source for io::Error {
+    fn with_context(self, context: Option<String>) -> KiokuErr {
+        KiokuErr::Io {
+            context,
+            source: self,
+        }
+    }
+}
+
+impl From<io::Error> for KiokuErr {
+    fn from(value: io::Error) -> Self {
+        value.with_context(None)
+    }
+}
+
+impl Source for git2::Error {
+    fn with_context(self, context: Option<String>) -> KiokuErr {
+        KiokuErr::Git {
+            context,
+            source: self,
+        }
+    }
+}
+
+impl From<git2::Error> for KiokuErr {
+    fn from(value: git2::Error) -> Self {
+        value.with_context(None)
+    }
+}
+
+impl Source for ureq::Error {
+    fn with_context(self, context: Option<String>) -> KiokuErr {
+        KiokuErr::Http {
+            context,
+            source: self,
+        }
+    }
+}
+
+impl From<ureq::Error> for KiokuErr {
+    fn from(value: ureq::Error) -> Self {
+        value.with_context(None)
+    }
+}
+
+impl Source for serde_json::Error {
+    fn with_context(self, context: Option<String>) -> KiokuErr {
+        KiokuErr::Serialization {
+            context,
+            source: self,
+        }
+    }
+}
+
+impl From<serde_json::Error> for KiokuErr {
+    fn from(value: serde_json::Error) -> Self {
+        value.with_context(None)
+    }
+}
+
+/// Like `anyhow::Context`, but keeping the error typed as the `KiokuErr` variant of its source
+pub trait TypedContext<T> {
+    fn typed_context(self, context: impl Into<String>) -> Result<T, KiokuErr>;
+
+    fn with_typed_context(self, context: impl FnOnce() -> String) -> Result<T, KiokuErr>;
+}
+
+impl<T, E: Source> TypedContext<T> for Result<T, E> {
+    fn typed_context(self, context: impl Into<String>) -> Result<T, KiokuErr> {
+        self.map_err(|e| e.with_context(Some(context.into())))
+    }
+
+    fn with_typed_context(self, context: impl FnOnce() -> String) -> Result<T, KiokuErr> {
+        self.map_err(|e| e.with_context(Some(context())))
+    }
+}
+
+/// Problems with wordlists
+#[derive(Debug, thiserror::Error)]
+pub enum WordlistErr {
+    #[error("Failed to read wordlist file {path}")]
+    Unreadable {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("No wordlist named {0} is installed")]
+    NotInstalled(String),
+    #[error(
+        "No wordlist for language {0} is bundled, install one with kioku wordlist add --lang {0}"
+    )]
+    NoLanguage(String),
+    #[error("Invalid wordlist name {0}, only ascii letters, digits, '-' and '_' are allowed")]
+    InvalidName(String),
+    #[error("Invalid language {0}, expected a code such as ja or pt-BR")]
+    InvalidLanguage(String),
+    #[error("Wordlist {0} does not contain any valid words")]
+    NoValidWords(String),
+    #[error("The wordlist does not contain any usable words")]
+    NoUsableWords(#[source] rand::distr::weighted::Error),
+    #[error("Checksum mismatch for wordlist {wordlist}: expected {expected}, got {actual}")]
+    Checksum {
+        wordlist: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Categories of failures, reported by exit code and as `error` by `--error-format json`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Error,
+    Declined,
+    Io,
+    Git,
+    Serialization,
+    /// The wordlist file, installed wordlist or language does not exist
+    WordlistMissing,
+    /// The wordlist contains no usable words
+    WordlistFormat,
+    MetadataWrite,
+    NamesExhausted,
+    /// A request to a server failed
+    Network,
+    /// A hook command failed
+    Hook,
+    /// A sealed metadata file was changed outside of kioku
+    Integrity,
+}
+
+impl Kind {
+    pub fn code(self) -> i32 {
+        match self {
+            Kind::Error | Kind::Io | Kind::Git | Kind::Serialization => 1,
+            Kind::Declined => 3,
+            Kind::WordlistMissing => 4,
+            Kind::WordlistFormat => 5,
+            Kind::MetadataWrite => 6,
+            Kind::NamesExhausted => 7,
+            Kind::Network => 8,
+            Kind::Hook => 9,
+            Kind::Integrity => 10,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Error => "error",
+            Kind::Declined => "declined",
+            Kind::Io => "io",
+            Kind::Git => "git",
+            Kind::Serialization => "serialization",
+            Kind::WordlistMissing => "wordlist_missing",
+            Kind::WordlistFormat => "wordlist_format",
+            Kind::MetadataWrite => "metadata_write",
+            Kind::NamesExhausted => "names_exhausted",
+            Kind::Network => "network",
+            Kind::Hook => "hook",
+            Kind::Integrity => "integrity",
+        }
+    }
+
+    /// The category of the outermost error in `chain` that has one
+    fn of_chain<'a>(chain: impl Iterator<Item = &'a (dyn std::error::Error + 'static)>) -> Kind {
+        for cause in chain {
+            if let Some(e) = cause.downcast_ref::<KiokuErr>() {
+                return e.kind();
+            }
+            if let Some(e) = cause.downcast_ref::<WordlistErr>() {
+                return e.kind();
+            }
+            if cause.is::<ureq::Error>() {
+                return Kind::Network;
+            }
+            if cause.is::<git2::Error>() {
+                return Kind::Git;
+            }
+            if cause.is::<serde_json::Error>() || cause.is::<csv::Error>() {
+                return Kind::Serialization;
+            }
+            if cause.is::<io::Error>() {
+                return Kind::Io;
+            }
+        }
+        Kind::Error
+    }
+}
+
+impl WordlistErr {
+    pub fn kind(&self) -> Kind {
+        match self {
+            WordlistErr::Unreadable { .. }
+            | WordlistErr::NotInstalled(_)
+            | WordlistErr::NoLanguage(_) => Kind::WordlistMissing,
+            WordlistErr::NoValidWords(_) | WordlistErr::NoUsableWords(_) => Kind::WordlistFormat,
+            WordlistErr::InvalidName(_)
+            | WordlistErr::InvalidLanguage(_)
+            | WordlistErr::Checksum { .. } => Kind::Error,
+        }
+    }
+}
+
+impl KiokuErr {
+    pub fn kind(&self) -> Kind {
+        match self {
+            KiokuErr::Exit(_) | KiokuErr::NoDataDir => Kind::Error,
+            KiokuErr::Declined(_) => Kind::Declined,
+            KiokuErr::Wordlist(e) => e.kind(),
+            KiokuErr::MetadataWrite { .. } => Kind::MetadataWrite,
+            KiokuErr::NamesExhausted { .. } => Kind::NamesExhausted,
+            KiokuErr::HookFailed { .. } => Kind::Hook,
+            KiokuErr::SealBroken { .. } => Kind::Integrity,
+            KiokuErr::ApplicationErr(e) => Kind::of_chain(e.chain()),
+            KiokuErr::Io { .. } => Kind::Io,
+            KiokuErr::Git { .. } => Kind::Git,
+            KiokuErr::Http { .. } => Kind::Network,
+            KiokuErr::Serialization { .. } => Kind::Serialization,
+        }
+    }
+
+    /// Stdout was closed, e.g. by `kioku | head`
+    pub fn is_broken_pipe(&self) -> bool {
+        let broken = |e: &io::Error| e.kind() == io::ErrorKind::BrokenPipe;
+        match self {
+            KiokuErr::Io { source, .. } => broken(source),
+            KiokuErr::ApplicationErr(e) => e
+                .chain()
+                .any(|cause| cause.downcast_ref::<io::Error>().is_some_and(broken)),
+            _ => false,
+        }
+    }
+}
+
+/// Exit code when stdout is closed early, as for processes killed by SIGPIPE
+pub const BROKEN_PIPE: i32 = 141;
+
+/// How errors are reported on stderr
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+pub enum ErrorFormat {
+    /// The error message
+    #[default]
+    Text,
+    /// A JSON object with the fields `error`, `code` and `message`
+    Json,
+}
+
+/// The error followed by its causes, as anyhow shows errors with `{:#}`
+fn message(error: &KiokuErr) -> String {
+    let mut message = error.to_string();
+    let mut cause = std::error::Error::source(error);
+    while let Some(e) = cause {
+        message.push_str(&format!(": {}", e));
+        cause = e.source();
+    }
+    message
+}
+
+/// Report an error on stderr and return the code to exit with
+pub fn report(format: ErrorFormat, error: &KiokuErr) -> i32 {
+    let kind = error.kind();
+    let message = message(error);
+    match format {
+        ErrorFormat::Text => eprintln!("{}", message),
+        ErrorFormat::Json => eprintln!(
+            "{}",
+            serde_json::json!({"error": kind.name(), "code": kind.code(), "message": message})
+        ),
+    }
+    kind.code()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn kinds_survive_context() {
+        let missing = WordlistErr::Unreadable {
+            path: "words.txt".to_string(),
+            source: io::Error::from(io::ErrorKind::NotFound),
+        };
+        let error = KiokuErr::from(Err::<(), _>(missing).context("Failed to load").unwrap_err());
+        assert_eq!(
+            error.to_string(),
+            "Failed to load: Failed to read wordlist file words.txt: entity not found"
+        );
+        assert_eq!(error.kind(), Kind::WordlistMissing);
+        let error: anyhow::Error = KiokuErr::NamesExhausted {
+            count: 3,
+            filtered: false,
+        }
+        .into();
+        let error = KiokuErr::from(error.context("Failed to start"));
+        assert_eq!(error.kind(), Kind::NamesExhausted);
+        assert_eq!(error.kind().code(), 7);
+        let error = KiokuErr::from(anyhow::Error::from(io::Error::other("disk")).context("x"));
+        assert_eq!(error.kind(), Kind::Io);
+        assert_eq!(KiokuErr::from(anyhow::anyhow!("other")).kind(), Kind::Error);
+    }
+
+    #[test]
+    fn typed_context_keeps_the_source() {
+        let error = Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+            .typed_context("Failed to read runs.jsonl")
+            .unwrap_err();
+        assert!(matches!(error, KiokuErr::Io { .. }));
+        assert_eq!(
+            message(&error),
+            "Failed to read runs.jsonl: entity not found"
+        );
+        let error = serde_json::from_str::<serde_json::Value>("{")
+            .with_typed_context(|| "Invalid record".to_string())
+            .unwrap_err();
+        assert_eq!(error.kind(), Kind::Serialization);
+        let error = KiokuErr::from(anyhow::Error::from(error).context("Failed to load"));
+        assert_eq!(error.kind(), Kind::Serialization);
+        assert_eq!(
+            KiokuErr::from(git2::Error::from_str("no HEAD")).kind(),
+            Kind::Git
+        );
+        let error = KiokuErr::from(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert_eq!(message(&error), "I/O error: broken pipe");
+        assert!(error.is_broken_pipe());
+        let error = Err::<(), _>(io::Error::from(io::ErrorKind::BrokenPipe))
+            .typed_context("Failed to write names")
+            .unwrap_err();
+        assert!(error.is_broken_pipe());
+        assert!(KiokuErr::from(anyhow::Error::from(error).context("x")).is_broken_pipe());
+    }
+}
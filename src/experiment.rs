@@ -0,0 +1,86 @@
+use anyhow::{Context, bail};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the metadata file placed in experiment directories
+pub static METADATA_FILE: &str = "meta.json";
+
+/// Name of the pointer to the most recent run
+pub static LATEST: &str = "latest";
+
+fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(to)
+        .with_context(|| format!("Failed to create directory {}", to.to_string_lossy()))?;
+    for entry in fs::read_dir(from)
+        .with_context(|| format!("Failed to read directory {}", from.to_string_lossy()))?
+    {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target).with_context(|| {
+                format!(
+                    "Failed to copy {} to {}",
+                    entry.path().to_string_lossy(),
+                    target.to_string_lossy()
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Create the directory of experiment `slug` under `root`, filled with a copy of `skeleton`.
+/// The namespace and prefix of a slug such as `vision/2024-06-12/bold-otter-paris` become
+/// directory levels.
+pub fn create(root: &Path, slug: &str, skeleton: Option<&Path>) -> anyhow::Result<PathBuf> {
+    if !slug.split('/').all(crate::platform::valid_file_name) {
+        bail!("{} cannot name a directory on every platform", slug);
+    }
+    let dir = root.join(slug);
+    if dir.exists() {
+        bail!(
+            "Experiment directory {} already exists",
+            dir.to_string_lossy()
+        );
+    }
+    match skeleton {
+        Some(skeleton) => copy_dir(skeleton, &dir)?,
+        None => fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create directory {}", dir.to_string_lossy()))?,
+    }
+    Ok(dir)
+}
+
+#[derive(serde::Serialize)]
+struct Pointer<'a> {
+    label: &'a str,
+    path: String,
+}
+
+/// Point `latest.json` in `dir` to the run `label` stored at `target`
+pub fn write_latest_pointer(dir: &Path, label: &str, target: &Path) -> anyhow::Result<()> {
+    let path = dir.join(format!("{}.json", LATEST));
+    crate::metadata::write_atomic(&path, |writer| {
+        serde_json::to_writer_pretty(
+            &mut *writer,
+            &Pointer {
+                label,
+                path: crate::platform::portable(target),
+            },
+        )?;
+        writer.write_all("\n".as_bytes())?;
+        Ok(())
+    })
+}
+
+/// Point `latest` in `root` to the run directory `dir`, a link where the platform allows one and
+/// a pointer file `latest.json` otherwise
+pub fn update_latest(root: &Path, label: &str, dir: &Path) -> anyhow::Result<()> {
+    if crate::platform::link_dir(dir, &root.join(LATEST))? {
+        return Ok(());
+    }
+    write_latest_pointer(root, label, dir)
+}
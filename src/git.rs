@@ -0,0 +1,63 @@
+use crate::error::TypedContext;
+use crate::metadata::MetaData;
+use anyhow::bail;
+
+/// How runs are recorded in the history of the repository they were started from
+#[derive(Clone, Copy, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GitRecord {
+    /// A git note on HEAD holding the metadata, one JSON line per run
+    Note,
+    /// A lightweight tag `exp/<name>` pointing at HEAD
+    Tag,
+}
+
+fn signature(repo: &git2::Repository) -> anyhow::Result<git2::Signature<'static>> {
+    Ok(repo
+        .signature()
+        .or_else(|_| git2::Signature::now("kioku", "kioku@localhost"))
+        .typed_context("Failed to create a git signature")?)
+}
+
+/// Attach the runs to the current HEAD commit
+pub fn record(kind: GitRecord, records: &[MetaData]) -> anyhow::Result<()> {
+    let repo = git2::Repository::discover(".")
+        .typed_context("--git-record needs to be run inside a git repository")?;
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .typed_context("Failed to find the HEAD commit")?;
+    match kind {
+        GitRecord::Note => {
+            let signature = signature(&repo)?;
+            // Runs started from the same commit share its note
+            let mut note = repo
+                .find_note(None, head.id())
+                .ok()
+                .and_then(|note| note.message().map(str::to_string))
+                .unwrap_or_default();
+            for meta in records {
+                if !note.is_empty() && !note.ends_with('\n') {
+                    note.push('\n');
+                }
+                note.push_str(
+                    &serde_json::to_string(meta).typed_context("Failed to serialize metadata")?,
+                );
+                note.push('\n');
+            }
+            repo.note(&signature, &signature, None, head.id(), &note, true)
+                .typed_context("Failed to write git note")?;
+        }
+        GitRecord::Tag => {
+            for meta in records {
+                let name = format!("exp/{}", meta.label);
+                if !git2::Reference::is_valid_name(&format!("refs/tags/{}", name)) {
+                    bail!("{} is not a valid git tag name", name);
+                }
+                repo.tag_lightweight(&name, head.as_object(), false)
+                    .with_typed_context(|| format!("Failed to create git tag {}", name))?;
+            }
+        }
+    }
+    Ok(())
+}
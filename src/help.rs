@@ -0,0 +1,323 @@
+use clap_mangen::roff::{Roff, bold, roman};
+use std::io::{self, Write};
+
+/// Keys of the configuration files along with what they set
+pub static CONFIG_KEYS: &[(&str, &str)] = &[
+    ("length", "Length of generated names in words, as --length"),
+    ("style", "How the words of a name are produced, as --style"),
+    (
+        "syllables",
+        "Syllables per word with style phonetic, as --syllables",
+    ),
+    (
+        "wordlist",
+        "Installed wordlist or path to a wordlist, as --words",
+    ),
+    (
+        "output",
+        "Metadata file, database or remote log runs are recorded in, as --output",
+    ),
+    ("format", "Format names are printed in, as --format"),
+    (
+        "template",
+        "Template for labels with placeholders {name}, {date} and {revision}",
+    ),
+    (
+        "prefix",
+        "Level placed before names, date, iso-week or text, as --prefix",
+    ),
+    (
+        "namespace",
+        "Namespace placed before names and prefixes, as --namespace",
+    ),
+    (
+        "separator",
+        "Separator placed between words, as --separator",
+    ),
+    ("case", "Casing of generated names, as --case"),
+    ("suffix", "Suffix making names unique, as --suffix"),
+    (
+        "exclude_file",
+        "File listing words never to use, as --exclude-file",
+    ),
+    (
+        "profanity_filter",
+        "Never use profanity when true, as --profanity-filter",
+    ),
+    (
+        "filter_cmd",
+        "Shell command validating candidate names, as --filter-cmd",
+    ),
+    (
+        "max_len",
+        "Maximum length of names in characters, as --max-len",
+    ),
+    (
+        "charset",
+        "Only generate names valid for this system, as --charset",
+    ),
+    (
+        "candidates",
+        "Number of names to pick from with --interactive, as --candidates",
+    ),
+    (
+        "mkdir",
+        "Create a directory for each run when true, as --mkdir",
+    ),
+    (
+        "experiments_dir",
+        "Directory run directories are created in, as --experiments-dir",
+    ),
+    (
+        "skeleton",
+        "Template directory copied into new run directories",
+    ),
+    (
+        "latest",
+        "Link `latest` to the newest run directory unless false, and next to metadata files if true",
+    ),
+    (
+        "wordlist_url",
+        "URL `kioku wordlist add` downloads from without a source",
+    ),
+    (
+        "wordlist_sha256",
+        "Expected SHA-256 checksum of wordlist_url",
+    ),
+    (
+        "wordlist_token",
+        "Bearer token sent when downloading wordlists",
+    ),
+    (
+        "wordlist_user",
+        "User for basic authentication when downloading wordlists",
+    ),
+    (
+        "wordlist_password",
+        "Password for basic authentication when downloading wordlists",
+    ),
+    (
+        "proxy",
+        "Proxy for downloads, overriding HTTPS_PROXY and HTTP_PROXY",
+    ),
+    (
+        "notify_url",
+        "URL the metadata of new runs is posted to, as --notify-url",
+    ),
+    (
+        "notify_headers",
+        "Table of HTTP headers sent with notifications",
+    ),
+    (
+        "git_record",
+        "Record runs in git as a note or tag, as --git-record",
+    ),
+    (
+        "repositories",
+        "Additional repositories whose revisions are recorded",
+    ),
+    (
+        "remote_user",
+        "User for basic authentication with an HTTP remote log",
+    ),
+    (
+        "remote_password",
+        "Password for basic authentication with an HTTP remote log",
+    ),
+    (
+        "commit_url",
+        "Link template for revisions in reports, {revision} is replaced",
+    ),
+    (
+        "scheduler",
+        "Batch scheduler whose jobs are recorded, as --scheduler",
+    ),
+    (
+        "capture_container",
+        "Record the container image when true, as --capture-container",
+    ),
+    ("lang", "Language of the default wordlist, as --lang"),
+    ("timestamp", "Time zone of recorded times, as --timestamp"),
+    (
+        "time_format",
+        "strftime format of recorded times, as --time-format",
+    ),
+    (
+        "pre_generate",
+        "Shell command run with the metadata of new runs before it is written",
+    ),
+    (
+        "post_generate",
+        "Shell command run with the metadata of new runs after it is written",
+    ),
+    (
+        "hook_failure",
+        "When a hook fails: abort (default), warn or ignore",
+    ),
+    (
+        "sealed",
+        "Chain the records of JSON lines files by hash when true, as --sealed",
+    ),
+    (
+        "seal_key",
+        "Key authenticating the seals of records with an HMAC",
+    ),
+];
+
+/// Exit codes and the failures they report, the `error` field of `--error-format json` in brackets
+pub static EXIT_CODES: &[(&str, &str)] = &[
+    ("0", "Success"),
+    ("1", "Any other error [error, io, git, serialization]"),
+    ("2", "Invalid command line arguments"),
+    ("3", "A confirmation prompt was declined [declined]"),
+    (
+        "4",
+        "The wordlist or its language does not exist [wordlist_missing]",
+    ),
+    (
+        "5",
+        "The wordlist contains no usable words [wordlist_format]",
+    ),
+    ("6", "A metadata file could not be written [metadata_write]"),
+    (
+        "7",
+        "Not enough distinct names meet the constraints [names_exhausted]",
+    ),
+    ("8", "A request to a server failed [network]"),
+    ("9", "A hook command failed [hook]"),
+    (
+        "10",
+        "A sealed metadata file fails verification [integrity]",
+    ),
+    ("141", "Stdout was closed early"),
+];
+
+/// Environment variables kioku reads or sets
+pub static ENVIRONMENT: &[(&str, &str)] = &[
+    ("KIOKU_CONFIG", "Path of the global configuration file"),
+    (
+        "KIOKU_<OPTION>",
+        "Value of an option such as --length, as listed with the option",
+    ),
+    (
+        "KIOKU_SEAL_KEY",
+        "Key authenticating the seals of records, overriding seal_key",
+    ),
+    (
+        "KIOKU_WORDLIST_URL",
+        "URL `kioku wordlist add` downloads from, overriding wordlist_url",
+    ),
+    (
+        "KIOKU_WORDLIST_SHA256",
+        "Expected SHA-256 checksum of KIOKU_WORDLIST_URL",
+    ),
+    (
+        "KIOKU_WORDLIST_TOKEN",
+        "Bearer token for wordlist downloads, overriding wordlist_token",
+    ),
+    (
+        "KIOKU_WORDLIST_PASSWORD",
+        "Password for wordlist downloads, overriding wordlist_password",
+    ),
+    (
+        "HTTPS_PROXY, HTTP_PROXY, ALL_PROXY",
+        "Proxy for wordlist downloads unless proxy is configured",
+    ),
+    ("NO_PROXY", "Hosts reached without the proxy"),
+    (
+        "RAYON_NUM_THREADS",
+        "Number of threads generating large batches of names",
+    ),
+    (
+        "KIOKU_NAME",
+        "Set to the generated name for commands started by `kioku run`",
+    ),
+    (
+        "KIOKU_RUN_ID",
+        "Set to the run id for commands started by `kioku run`",
+    ),
+    (
+        "MLFLOW_TRACKING_URI",
+        "MLflow tracking server used by --mlflow without a URI",
+    ),
+    (
+        "MLFLOW_EXPERIMENT_ID",
+        "MLflow experiment runs are created in, defaults to 0",
+    ),
+    (
+        "MLFLOW_TRACKING_TOKEN",
+        "Bearer token sent to the MLflow tracking server",
+    ),
+    (
+        "WANDB_API_KEY",
+        "API key used to register runs with --wandb",
+    ),
+    (
+        "WANDB_BASE_URL",
+        "W&B server used by --wandb, defaults to https://api.wandb.ai",
+    ),
+];
+
+static CONFIG_FILES: &str = "Settings are read from the global configuration file ($KIOKU_CONFIG or \
+config.toml in the platform configuration directory) and from .kioku.toml in the current directory \
+or the repository root, which takes precedence. Environment variables named after an option override both, command line flags override everything.";
+
+fn write_table(out: &mut impl Write, entries: &[(&str, &str)]) -> io::Result<()> {
+    let width = entries.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, description) in entries {
+        writeln!(out, "  {:width$}  {}", key, description, width = width)?;
+    }
+    Ok(())
+}
+
+/// Print the help of every command followed by the configuration keys and environment variables
+pub fn print_all(cmd: &mut clap::Command, out: &mut impl Write) -> io::Result<()> {
+    cmd.build();
+    write!(out, "{}", cmd.render_long_help())?;
+    for sub in cmd.get_subcommands_mut().filter(|sub| !sub.is_hide_set()) {
+        write_subcommand(sub, out)?;
+    }
+    writeln!(out, "\nConfiguration:\n  {}\n", CONFIG_FILES)?;
+    write_table(out, CONFIG_KEYS)?;
+    writeln!(out, "\nEnvironment:")?;
+    write_table(out, ENVIRONMENT)?;
+    writeln!(out, "\nExit status:")?;
+    write_table(out, EXIT_CODES)
+}
+
+fn write_subcommand(cmd: &mut clap::Command, out: &mut impl Write) -> io::Result<()> {
+    let name = cmd.get_bin_name().unwrap_or(cmd.get_name()).to_string();
+    writeln!(out, "\n{}\n{}", name, "=".repeat(name.len()))?;
+    write!(out, "{}", cmd.render_long_help())?;
+    for sub in cmd.get_subcommands_mut().filter(|sub| !sub.is_hide_set()) {
+        write_subcommand(sub, out)?;
+    }
+    Ok(())
+}
+
+/// Render the man page, with sections on configuration and environment after the generated ones
+pub fn man(cmd: clap::Command, out: &mut impl Write) -> io::Result<()> {
+    clap_mangen::Man::new(cmd).render(out)?;
+    let mut roff = Roff::default();
+    roff.control("SH", ["CONFIGURATION"]);
+    roff.text([roman(CONFIG_FILES)]);
+    for (key, description) in CONFIG_KEYS {
+        roff.control("TP", []);
+        roff.text([bold(*key)]);
+        roff.text([roman(*description)]);
+    }
+    roff.control("SH", ["ENVIRONMENT"]);
+    for (var, description) in ENVIRONMENT {
+        roff.control("TP", []);
+        roff.text([bold(*var)]);
+        roff.text([roman(*description)]);
+    }
+    roff.control("SH", ["EXIT STATUS"]);
+    for (code, description) in EXIT_CODES {
+        roff.control("TP", []);
+        roff.text([bold(*code)]);
+        roff.text([roman(*description)]);
+    }
+    // Without the preamble, which the generated page already starts with
+    out.write_all(roff.to_roff().as_bytes())
+}
@@ -0,0 +1,98 @@
+use crate::error::KiokuErr;
+use crate::metadata::MetaData;
+use anyhow::Context;
+use std::io::{self, Write};
+use std::process::Stdio;
+
+/// Point in the generation of runs a hook command is run at
+#[derive(Clone, Copy)]
+pub enum Hook {
+    /// Names and metadata are generated, nothing is written yet
+    PreGenerate,
+    /// The metadata is written
+    PostGenerate,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreGenerate => "pre_generate",
+            Hook::PostGenerate => "post_generate",
+        }
+    }
+}
+
+/// What happens when a hook command fails
+#[derive(Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnFailure {
+    /// Stop with an error, before anything is written for pre_generate
+    #[default]
+    Abort,
+    /// Print a warning and carry on
+    Warn,
+    /// Carry on silently
+    Ignore,
+}
+
+/// Run the shell command of `hook` with the records as JSON lines on stdin. `$KIOKU_HOOK` names
+/// the hook and `$KIOKU_COUNT` gives the number of runs, a single run is also passed as
+/// `$KIOKU_NAME` and `$KIOKU_RUN_ID`. The output of the command goes to stderr, keeping stdout
+/// free for the generated names.
+fn run(hook: Hook, command: &str, records: &[MetaData]) -> anyhow::Result<()> {
+    log::debug!("Running {} hook {}", hook.name(), command);
+    let mut shell = crate::run::shell(command);
+    shell
+        .env("KIOKU_HOOK", hook.name())
+        .env("KIOKU_COUNT", records.len().to_string());
+    if let [meta] = records {
+        shell.env("KIOKU_NAME", &meta.label);
+        if let Some(run_id) = &meta.run_id {
+            shell.env("KIOKU_RUN_ID", run_id);
+        }
+    }
+    let mut child = shell
+        .stdin(Stdio::piped())
+        .stdout(io::stderr())
+        .spawn()
+        .with_context(|| format!("Failed to run {} hook {}", hook.name(), command))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Hooks may exit without reading the metadata
+        match stdin.write_all(crate::remote::jsonl(records)?.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => {}
+        }
+    }
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for {} hook {}", hook.name(), command))?;
+    if !status.success() {
+        return Err(KiokuErr::HookFailed {
+            hook: hook.name(),
+            command: command.to_string(),
+            status,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Run `hook` if a command is configured for it, handling failures as `on_failure` says
+pub fn trigger(
+    hook: Hook,
+    command: Option<&String>,
+    records: &[MetaData],
+    on_failure: OnFailure,
+) -> anyhow::Result<()> {
+    let Some(command) = command else {
+        return Ok(());
+    };
+    match (run(hook, command, records), on_failure) {
+        (Err(e), OnFailure::Abort) => Err(e),
+        (Err(e), OnFailure::Warn) => {
+            log::warn!("{:#}", e);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
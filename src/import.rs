@@ -0,0 +1,444 @@
+use crate::metadata::{MetaData, SCHEMA_VERSION, new_run_id};
+use crate::mlflow::MlflowRun;
+use crate::store;
+use crate::wandb::WandbRun;
+use anyhow::{Context, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde_json::{Map, Value, json};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Where runs are imported from
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Source {
+    /// Runs of an experiment on an MLflow tracking server
+    Mlflow,
+    /// Runs of a W&B project, given as <ENTITY/PROJECT>
+    Wandb,
+    /// A CSV file with a header row
+    Csv,
+    /// A JSON lines file, or a JSON file holding an array of records
+    Jsonl,
+}
+
+/// Field names other tools use for the fields of kioku records, checked in order
+static ALIASES: &[(&str, &[&str])] = &[
+    (
+        "label",
+        &[
+            "label",
+            "name",
+            "run_name",
+            "runName",
+            "display_name",
+            "displayName",
+            "slug",
+        ],
+    ),
+    (
+        "timestamp",
+        &[
+            "timestamp",
+            "start_time",
+            "startTime",
+            "started",
+            "created",
+            "createdAt",
+            "created_at",
+        ],
+    ),
+    (
+        "revision",
+        &[
+            "revision",
+            "commit",
+            "git_commit",
+            "git_sha",
+            "mlflow.source.git.commit",
+        ],
+    ),
+    (
+        "finished",
+        &["finished", "end_time", "endTime", "finished_at"],
+    ),
+    ("status", &["status", "state"]),
+    ("tags", &["tags"]),
+];
+
+/// Convert a time given as seconds or milliseconds since the epoch, or as a date and time with
+/// or without offset (taken as UTC), into the RFC 3339 timestamps kioku records
+fn normalize_time(value: &Value) -> Option<String> {
+    let utc = match value {
+        Value::Number(n) => {
+            let n = n.as_f64()?;
+            let millis = if n > 1e11 { n } else { n * 1000.0 };
+            chrono::DateTime::from_timestamp_millis(millis as i64)?
+        }
+        Value::String(s) => {
+            if let Ok(time) = chrono::DateTime::parse_from_rfc3339(s) {
+                return Some(
+                    time.to_utc()
+                        .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+                );
+            }
+            [
+                "%Y-%m-%dT%H:%M:%S%.f",
+                "%Y-%m-%d %H:%M:%S%.f",
+                "%Y-%m-%d %H:%M",
+            ]
+            .iter()
+            .find_map(|format| chrono::NaiveDateTime::parse_from_str(s, format).ok())?
+            .and_utc()
+        }
+        _ => return None,
+    };
+    Some(utc.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+}
+
+fn normalize_status(value: &Value) -> Option<&'static str> {
+    match value.as_str()?.to_ascii_lowercase().as_str() {
+        "running" | "scheduled" | "pending" => Some("running"),
+        "success" | "finished" | "completed" | "succeeded" => Some("success"),
+        "failed" | "crashed" | "killed" | "error" => Some("failed"),
+        _ => None,
+    }
+}
+
+fn is_uuid(id: &str) -> bool {
+    id.len() == 36
+        && id.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Turn the fields of a record from another tool into a kioku record.
+/// Fields without a kioku counterpart are kept as they are, except for the seals of records
+/// from sealed kioku files.
+pub fn from_fields(mut fields: Map<String, Value>) -> anyhow::Result<MetaData> {
+    crate::seal::strip(&mut fields);
+    let mut record = Map::new();
+    record.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+    for (field, aliases) in ALIASES {
+        let value = aliases
+            .iter()
+            .filter_map(|alias| fields.remove(*alias))
+            .find(|value| !value.is_null());
+        let Some(value) = value else {
+            continue;
+        };
+        let value = match *field {
+            "timestamp" | "finished" => match normalize_time(&value) {
+                Some(time) => Value::String(time),
+                None => bail!("Unrecognized time {}", value),
+            },
+            "status" => match normalize_status(&value) {
+                Some(status) => Value::String(status.to_string()),
+                None => {
+                    fields.insert("source_status".to_string(), value);
+                    continue;
+                }
+            },
+            "tags" => match value {
+                Value::String(tags) => tags
+                    .split([',', ';'])
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| Value::String(tag.to_string()))
+                    .collect(),
+                value => value,
+            },
+            "label" | "revision" => match value {
+                Value::String(s) => Value::String(s),
+                value => Value::String(value.to_string()),
+            },
+            _ => value,
+        };
+        record.insert(field.to_string(), value);
+    }
+    if !record.contains_key("label") {
+        bail!("Record has no name");
+    }
+    if !record.contains_key("timestamp") {
+        bail!("Record has no start time");
+    }
+    if !record.contains_key("revision") {
+        record.insert("revision".to_string(), Value::Null);
+    }
+    if let (Some(start), Some(end)) = (
+        record.get("timestamp").and_then(Value::as_str),
+        record.get("finished").and_then(Value::as_str),
+    ) && !fields.contains_key("duration_secs")
+        && let (Ok(start), Ok(end)) = (
+            chrono::DateTime::parse_from_rfc3339(start),
+            chrono::DateTime::parse_from_rfc3339(end),
+        )
+    {
+        record.insert(
+            "duration_secs".to_string(),
+            (end - start).as_seconds_f64().into(),
+        );
+    }
+    // Ids of other tools need not be UUIDs, these are kept apart from kioku's own
+    match fields.remove("run_id") {
+        Some(Value::String(id)) if is_uuid(&id) => {
+            record.insert("run_id".to_string(), id.into());
+        }
+        other => {
+            if let Some(id) = other {
+                fields.entry("source_run_id").or_insert(id);
+            }
+            record.insert("run_id".to_string(), new_run_id().into());
+        }
+    }
+    fields.remove("schema_version");
+    for (key, value) in fields {
+        record.entry(key).or_insert(value);
+    }
+    Ok(serde_json::from_value(Value::Object(record))?)
+}
+
+fn mlflow_runs(tracking_uri: &str, experiment: &str) -> anyhow::Result<Vec<Map<String, Value>>> {
+    let url = format!(
+        "{}/api/2.0/mlflow/runs/search",
+        tracking_uri.trim_end_matches('/')
+    );
+    let mut runs = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut request = ureq::post(&url).header("Content-Type", "application/json");
+        if let Ok(token) = env::var("MLFLOW_TRACKING_TOKEN") {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        let body = json!({
+            "experiment_ids": [experiment],
+            "max_results": 1000,
+            "page_token": page_token,
+        });
+        let response: Value = request
+            .send(body.to_string())
+            .and_then(|mut resp| resp.body_mut().read_to_string())
+            .map_err(anyhow::Error::from)
+            .and_then(|body| Ok(serde_json::from_str(&body)?))
+            .with_context(|| format!("MLflow request to {} failed", url))?;
+        for run in response["runs"].as_array().into_iter().flatten() {
+            let info = &run["info"];
+            let mut fields = Map::new();
+            for tag in run["data"]["tags"].as_array().into_iter().flatten() {
+                let (Some(key), Some(value)) = (tag["key"].as_str(), tag["value"].as_str()) else {
+                    continue;
+                };
+                match key {
+                    "mlflow.source.git.commit" => {
+                        fields.insert("revision".to_string(), value.into());
+                    }
+                    "kioku.tags" => {
+                        fields.insert("tags".to_string(), value.into());
+                    }
+                    _ if key.starts_with("mlflow.") || key.starts_with("kioku.") => {}
+                    _ => {
+                        fields.insert(key.to_string(), value.into());
+                    }
+                }
+            }
+            fields.insert("label".to_string(), info["run_name"].clone());
+            fields.insert("timestamp".to_string(), info["start_time"].clone());
+            fields.insert("finished".to_string(), info["end_time"].clone());
+            fields.insert("status".to_string(), info["status"].clone());
+            if let Some(run_id) = info["run_id"].as_str() {
+                let mlflow = MlflowRun {
+                    tracking_uri: tracking_uri.to_string(),
+                    run_id: run_id.to_string(),
+                };
+                fields.insert("mlflow".to_string(), serde_json::to_value(mlflow)?);
+            }
+            runs.push(fields);
+        }
+        match response["next_page_token"].as_str() {
+            Some(token) if !token.is_empty() => page_token = Some(token.to_string()),
+            _ => return Ok(runs),
+        }
+    }
+}
+
+static WANDB_RUNS: &str = "query Runs($entity: String!, $project: String!, $cursor: String) {
+  project(name: $project, entityName: $entity) {
+    runs(first: 100, after: $cursor) {
+      edges { node { name displayName createdAt heartbeatAt state commit tags } }
+      pageInfo { endCursor hasNextPage }
+    }
+  }
+}";
+
+fn wandb_runs(target: &str) -> anyhow::Result<Vec<Map<String, Value>>> {
+    let Some((entity, project)) = target.split_once('/') else {
+        bail!(
+            "Invalid W&B project {}, expected <ENTITY>/<PROJECT>",
+            target
+        );
+    };
+    let api_key = env::var("WANDB_API_KEY").context("WANDB_API_KEY is not set")?;
+    let base_url = env::var("WANDB_BASE_URL").unwrap_or_else(|_| "https://api.wandb.ai".into());
+    let url = format!("{}/graphql", base_url.trim_end_matches('/'));
+    let mut runs = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let body = json!({
+            "query": WANDB_RUNS,
+            "variables": {"entity": entity, "project": project, "cursor": cursor},
+        });
+        let response: Value = ureq::post(&url)
+            .header("Content-Type", "application/json")
+            .header(
+                "Authorization",
+                format!("Basic {}", STANDARD.encode(format!("api:{}", api_key))),
+            )
+            .send(body.to_string())
+            .and_then(|mut resp| resp.body_mut().read_to_string())
+            .map_err(anyhow::Error::from)
+            .and_then(|body| Ok(serde_json::from_str(&body)?))
+            .with_context(|| format!("W&B request to {} failed", url))?;
+        if let Some(errors) = response.get("errors") {
+            bail!("W&B rejected the query: {}", errors);
+        }
+        let page = &response["data"]["project"]["runs"];
+        for edge in page["edges"].as_array().into_iter().flatten() {
+            let node = &edge["node"];
+            let mut fields = Map::new();
+            fields.insert("label".to_string(), node["displayName"].clone());
+            fields.insert("timestamp".to_string(), node["createdAt"].clone());
+            fields.insert("revision".to_string(), node["commit"].clone());
+            fields.insert("tags".to_string(), node["tags"].clone());
+            fields.insert("status".to_string(), node["state"].clone());
+            if node["state"].as_str() != Some("running") {
+                fields.insert("finished".to_string(), node["heartbeatAt"].clone());
+            }
+            if let Some(run_id) = node["name"].as_str() {
+                let wandb = WandbRun {
+                    entity: entity.to_string(),
+                    project: project.to_string(),
+                    run_id: run_id.to_string(),
+                };
+                fields.insert("wandb".to_string(), serde_json::to_value(wandb)?);
+            }
+            runs.push(fields);
+        }
+        match page["pageInfo"]["endCursor"].as_str() {
+            Some(end) if page["pageInfo"]["hasNextPage"] == true => cursor = Some(end.to_string()),
+            _ => return Ok(runs),
+        }
+    }
+}
+
+/// Records of a JSON lines file, or of a JSON file holding an array of records. Of sealed files
+/// only the current records are read.
+fn read_json(path: &Path) -> anyhow::Result<Vec<Value>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.to_string_lossy()))?;
+    let values = serde_json::Deserializer::from_str(&contents)
+        .into_iter::<Value>()
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse {}", path.to_string_lossy()))?;
+    Ok(match values.as_slice() {
+        [Value::Array(records)] => records.clone(),
+        _ if crate::seal::is_sealed(&values) => crate::seal::current(values),
+        _ => values,
+    })
+}
+
+fn objects(location: &str, values: Vec<Value>) -> anyhow::Result<Vec<Map<String, Value>>> {
+    values
+        .into_iter()
+        .map(|value| match value {
+            Value::Object(fields) => Ok(fields),
+            _ => bail!("{} contains a record that is not an object", location),
+        })
+        .collect()
+}
+
+/// Read the runs recorded in `location` by another tool as kioku records
+pub fn import(
+    source: Source,
+    location: &str,
+    experiment: Option<&str>,
+) -> anyhow::Result<Vec<MetaData>> {
+    let runs = match source {
+        Source::Mlflow => mlflow_runs(
+            location,
+            &experiment
+                .map(str::to_string)
+                .or_else(|| env::var("MLFLOW_EXPERIMENT_ID").ok())
+                .unwrap_or_else(|| "0".to_string()),
+        )?,
+        Source::Wandb => wandb_runs(location)?,
+        Source::Csv => objects(
+            location,
+            store::read_csv(Path::new(location))
+                .with_context(|| format!("Failed to read CSV file {}", location))?,
+        )?,
+        Source::Jsonl => objects(location, read_json(Path::new(location))?)?,
+    };
+    runs.into_iter()
+        .enumerate()
+        .map(|(i, fields)| {
+            from_fields(fields).with_context(|| format!("Failed to import record {}", i + 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_of_other_tools_are_mapped() {
+        let fields = json!({
+            "name": "baseline",
+            "start_time": 1768462809000_i64,
+            "end_time": 1768462869500_i64,
+            "state": "FINISHED",
+            "commit": "84cf86e2",
+            "tags": "a, b",
+            "lr": 0.1,
+            "seal": {"seq": 0, "prev": "0", "hash": "0"},
+        });
+        let Value::Object(fields) = fields else {
+            unreachable!()
+        };
+        let meta = from_fields(fields).unwrap();
+        assert_eq!(meta.label, "baseline");
+        assert_eq!(meta.revision.as_deref(), Some("84cf86e2"));
+        assert_eq!(meta.tags, ["a", "b"]);
+        assert!(meta.status == Some(crate::metadata::Status::Success));
+        assert_eq!(meta.duration_secs, Some(60.5));
+        assert_eq!(
+            chrono::DateTime::parse_from_rfc3339(&meta.timestamp)
+                .unwrap()
+                .timestamp(),
+            1768462809
+        );
+        assert_eq!(meta.extra.get("lr"), Some(&json!(0.1)));
+        assert!(!meta.extra.contains_key(crate::seal::FIELD));
+    }
+
+    #[test]
+    fn files_are_read_as_the_chosen_source() {
+        let path = std::env::temp_dir().join(format!("kioku-import-{}.txt", std::process::id()));
+        std::fs::write(&path, "name,start_time\nbaseline,2026-01-15T07:40:09Z\n").unwrap();
+        let runs = import(Source::Csv, &path.to_string_lossy(), None).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].label, "baseline");
+        assert!(import(Source::Jsonl, &path.to_string_lossy(), None).is_err());
+
+        std::fs::write(
+            &path,
+            "{\"name\": \"baseline\", \"start_time\": \"2026-01-15T07:40:09Z\"}\n{\"name\": \"sweep\", \"start_time\": \"2026-01-16T07:40:09Z\"}\n",
+        )
+        .unwrap();
+        let runs = import(Source::Jsonl, &path.to_string_lossy(), None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let labels: Vec<&str> = runs.iter().map(|meta| meta.label.as_str()).collect();
+        assert_eq!(labels, ["baseline", "sweep"]);
+    }
+}
@@ -0,0 +1,37 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Writes log messages to stderr as plain lines, keeping stdout free for names
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Details are only of interest from kioku itself, not from its dependencies
+        metadata.level() <= log::max_level()
+            && (metadata.level() <= Level::Warn
+                || metadata.target().starts_with(env!("CARGO_CRATE_NAME")))
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Informational messages are shown by default, `quiet` silences everything but errors and each
+/// `verbose` level adds more detail
+pub fn init(quiet: bool, verbose: u8) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -1,184 +1,2205 @@
 use anyhow::Context;
-use clap::Parser;
-use rand::prelude::*;
-use std::fmt;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use error::KiokuErr;
+use sink::MetadataSink;
+use std::collections::HashSet;
 use std::fs;
-use std::io::Write;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+mod artifact;
+mod bundle;
+mod config;
+mod container;
+mod diff;
+mod error;
+mod experiment;
+mod git;
+mod help;
+mod hook;
+mod import;
+mod logging;
+mod metadata;
+mod mlflow;
+mod mnemonic;
+mod name;
+mod notify;
+mod platform;
+mod prune;
+mod remote;
+mod report;
+mod run;
+mod scheduler;
+mod seal;
+mod serve;
+mod sink;
+mod store;
+mod tui;
+mod wandb;
+mod wordlist;
 
 #[derive(Parser)]
 #[command(version, about="Generate random human-readable strings for naming experiments and log associated metadata", long_about = None)] // Read from `Cargo.toml`
-
 struct Cli {
-    /// Length of the generated name in words
-    #[arg(short, long, value_name = "LENGTH", default_value = "3")]
-    length: usize,
-    /// Output metadata in JSON format to <FILE>
-    #[arg(short, long, value_name = "FILE")]
+    #[command(subcommand)]
+    command: Option<Commands>,
+    #[command(flatten)]
+    generate: GenerateArgs,
+    /// Print nothing but the generated names and errors
+    #[arg(
+        short,
+        long,
+        global = true,
+        conflicts_with = "verbose",
+        env = "KIOKU_QUIET",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    quiet: bool,
+    /// Report what kioku is doing, repeat for more detail
+    #[arg(
+        short,
+        long,
+        global = true,
+        action = clap::ArgAction::Count,
+        env = "KIOKU_VERBOSE"
+    )]
+    verbose: u8,
+    /// Print help for all commands, configuration keys and environment variables
+    #[arg(long)]
+    help_all: bool,
+    /// Answer yes to all prompts
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "KIOKU_YES",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    yes: bool,
+    /// Never prompt, declining anything that needs confirmation (implied when stdin is not a terminal)
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "yes",
+        env = "KIOKU_NO_INPUT",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    no_input: bool,
+    /// Report errors as text or as a JSON object on stderr
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        env = "KIOKU_ERROR_FORMAT",
+        default_value_t
+    )]
+    error_format: error::ErrorFormat,
+}
+
+#[derive(Args, Clone)]
+struct GenerateArgs {
+    /// Length of the generated name in words [default: 3]
+    #[arg(short, long, value_name = "LENGTH", env = "KIOKU_LENGTH")]
+    length: Option<usize>,
+    /// Output metadata to <FILE>, as JSON, JSON lines (.jsonl), CSV (.csv) or SQLite (.db) depending on the extension, a remote log or stdout (-)
+    #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
     output: Option<String>,
-    /// Specify wordlist to use
-    #[arg(short, long, value_name = "WORDLIST")]
-    words: Option<std::path::PathBuf>,
+    /// How the words of the name are produced
+    #[arg(long, value_enum, value_name = "STYLE", env = "KIOKU_STYLE")]
+    style: Option<name::Generator>,
+    /// Number of syllables per word with --style phonetic [default: 2]
+    #[arg(long, value_name = "COUNT", env = "KIOKU_SYLLABLES")]
+    syllables: Option<usize>,
+    /// Specify wordlist to use, either the name of an installed wordlist, a path or - for stdin
+    #[arg(short, long, value_name = "WORDLIST", env = "KIOKU_WORDLIST")]
+    words: Option<String>,
+    /// Language of the default wordlist, e.g. ja, used when --words is not given [default: en]
+    #[arg(long, value_name = "LANG", env = "KIOKU_LANG")]
+    lang: Option<String>,
+    /// Place date (YYYY-MM-DD), iso-week (YYYY-Www), none or any other text before the name,
+    /// separated by '/'
+    #[arg(long, value_name = "PREFIX", env = "KIOKU_PREFIX")]
+    prefix: Option<name::Prefix>,
+    /// Place <NAME> before the name and prefix, separated by '/'. Names already recorded in the
+    /// namespace are not generated again.
+    #[arg(long, value_name = "NAME", env = "KIOKU_NAMESPACE", value_parser = name::parse_namespace)]
+    namespace: Option<String>,
+    /// Separator placed between words, defaults to '-' or the separator of the chosen case
+    #[arg(short, long, value_name = "SEPARATOR", env = "KIOKU_SEPARATOR")]
+    separator: Option<String>,
+    /// Derive the name from the SHA-256 hash of <FILE> (or stdin for -), so equal contents get equal names
+    #[arg(long, value_name = "FILE", env = "KIOKU_FROM_HASH")]
+    from_hash: Option<String>,
+    /// Number of distinct names to generate. Plain names are printed as they are generated, so
+    /// some may be printed before kioku fails for lack of distinct names.
+    #[arg(
+        short = 'n',
+        long,
+        value_name = "COUNT",
+        env = "KIOKU_COUNT",
+        default_value = "1"
+    )]
+    count: usize,
+    /// Pick the name among several candidates in a prompt, pressing r for new ones
+    #[arg(short, long, conflicts_with = "from_hash")]
+    interactive: bool,
+    /// Number of names to pick from with --interactive [default: 5]
+    #[arg(long, value_name = "COUNT", env = "KIOKU_CANDIDATES")]
+    candidates: Option<usize>,
+    /// Print the run id after each name, separated by a tab
+    #[arg(long)]
+    print_id: bool,
+    /// Also print the metadata of each name as a JSON line on stderr
+    #[arg(
+        short = 'm',
+        long,
+        env = "KIOKU_EMIT_META",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    emit_meta: bool,
+    /// Merge the fields of a JSON object read from stdin into the metadata, e.g. a whole config
+    #[arg(long)]
+    meta_stdin: bool,
+    /// Print names and their metadata to stdout in this format
+    #[arg(short, long, value_enum, value_name = "FORMAT", env = "KIOKU_FORMAT")]
+    format: Option<metadata::Format>,
+    /// Casing of the generated name
+    #[arg(short, long, value_enum, value_name = "CASE", env = "KIOKU_CASE")]
+    case: Option<name::Case>,
+    /// Never use the words listed in <FILE>, one per line
+    #[arg(long, value_name = "FILE", env = "KIOKU_EXCLUDE_FILE")]
+    exclude_file: Option<String>,
+    /// Never use words from the built-in list of profanity
+    #[arg(
+        long,
+        env = "KIOKU_PROFANITY_FILTER",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    profanity_filter: bool,
+    /// Maximum length of the name in characters (graphemes), including namespace and prefix,
+    /// longer names are resampled
+    #[arg(long, value_name = "LENGTH", env = "KIOKU_MAX_LEN")]
+    max_len: Option<usize>,
+    /// Pipe each candidate name through the shell command <CMD>, resampling when it exits non-zero
+    #[arg(long, value_name = "CMD", env = "KIOKU_FILTER_CMD")]
+    filter_cmd: Option<String>,
+    /// Only generate names valid for this target system
+    #[arg(long, value_enum, value_name = "CHARSET", env = "KIOKU_CHARSET")]
+    charset: Option<name::Charset>,
+    /// Append a suffix to the name to make it unique
+    #[arg(long, value_enum, value_name = "SUFFIX", env = "KIOKU_SUFFIX")]
+    suffix: Option<name::Suffix>,
+    /// Tag the run, can be given multiple times ($KIOKU_TAG holds comma separated tags)
+    #[arg(
+        long = "tag",
+        value_name = "TAG",
+        env = "KIOKU_TAG",
+        value_delimiter = ','
+    )]
+    tags: Vec<String>,
+    /// Create a directory for each run holding its metadata, optionally copied from a template directory
+    #[arg(long, value_name = "TEMPLATE", num_args = 0..=1, env = "KIOKU_MKDIR")]
+    mkdir: Option<Option<String>>,
+    /// Mirror the run in MLflow, using the tracking server at <URI> or $MLFLOW_TRACKING_URI
+    #[arg(long, value_name = "URI", num_args = 0..=1, env = "KIOKU_MLFLOW")]
+    mlflow: Option<Option<String>>,
+    /// Register the run with W&B in <ENTITY/PROJECT>, authenticating with $WANDB_API_KEY
+    #[arg(long, value_name = "ENTITY/PROJECT", env = "KIOKU_WANDB")]
+    wandb: Option<String>,
+    /// Record the run in the git history, as a note on HEAD or a tag exp/<name>
+    #[arg(long, value_enum, value_name = "KIND", env = "KIOKU_GIT_RECORD")]
+    git_record: Option<git::GitRecord>,
+    /// POST the metadata of each run as JSON to <URL>
+    #[arg(long, value_name = "URL", env = "KIOKU_NOTIFY_URL")]
+    notify_url: Option<String>,
+    /// Record the SLURM or PBS job the run is started in [default: auto]
+    #[arg(long, value_enum, value_name = "SCHEDULER", env = "KIOKU_SCHEDULER")]
+    scheduler: Option<scheduler::Scheduler>,
+    /// Record the image digest, id and OCI labels of the container the run is started in
+    #[arg(
+        long,
+        env = "KIOKU_CAPTURE_CONTAINER",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    capture_container: bool,
+    /// Time zone of the recorded start and end times [default: utc]
+    #[arg(long, value_enum, value_name = "ZONE", env = "KIOKU_TIMESTAMP")]
+    timestamp: Option<metadata::Zone>,
+    /// strftime format of the recorded start and end times instead of RFC 3339
+    #[arg(long, value_name = "FORMAT", env = "KIOKU_TIME_FORMAT")]
+    time_format: Option<String>,
+    /// Directory in which --mkdir creates run directories [default: experiments]
+    #[arg(long, value_name = "DIR", env = "KIOKU_EXPERIMENTS_DIR")]
+    experiments_dir: Option<String>,
+    /// Chain the records of the JSON lines metadata file by hash, so later edits are detected by
+    /// kioku verify-log. Files stay sealed once they are.
+    #[arg(
+        long,
+        env = "KIOKU_SEALED",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    sealed: bool,
+    /// Print the names and the metadata that would be recorded without writing any file or
+    /// contacting integrations
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Manage installed wordlists
+    #[command(subcommand)]
+    Wordlist(WordlistCommands),
+    /// Record output files of runs and check them for modification
+    #[command(subcommand)]
+    Artifact(ArtifactCommands),
+    /// Generate a name and record the start of a run
+    Start {
+        #[command(flatten)]
+        generate: GenerateArgs,
+    },
+    /// Record the end of a run
+    Finish {
+        /// Label or run id of the run
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        slug: String,
+        /// Outcome of the run
+        #[arg(long, value_enum, default_value = "success")]
+        status: metadata::Status,
+        /// Note attached to the run
+        #[arg(long, value_name = "TEXT")]
+        note: Option<String>,
+        /// Metadata file the run was recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Run a command under a generated name, recording its start, exit code and duration
+    Run {
+        #[command(flatten)]
+        generate: GenerateArgs,
+        /// Capture the standard output of the command to <FILE>, {name} is replaced by the name
+        #[arg(long, value_name = "FILE")]
+        stdout: Option<String>,
+        /// Capture the standard error of the command to <FILE>, {name} is replaced by the name
+        #[arg(long, value_name = "FILE")]
+        stderr: Option<String>,
+        /// Command to run, the generated name is available as $KIOKU_NAME and its run id as $KIOKU_RUN_ID
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Add or remove tags of a recorded run
+    Tag {
+        /// Label or run id of the run
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        slug: String,
+        /// Tags to add as +TAG (or TAG) and to remove as -TAG, options must come before these
+        #[arg(required = true, allow_hyphen_values = true, value_name = "+TAG|-TAG")]
+        changes: Vec<String>,
+        /// Metadata file the run was recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Change the label of a recorded run, keeping the old label as an alias that still finds it
+    Rename {
+        /// Label or run id of the run
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        slug: String,
+        /// New label of the run
+        new: String,
+        /// Metadata file the run was recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Attach a timestamped note to a recorded run
+    Note {
+        /// Label or run id of the run
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        slug: String,
+        /// Text of the note
+        message: String,
+        /// Metadata file the run was recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Append a timestamped event, such as a checkpoint, to a recorded run
+    Log {
+        /// Label or run id of the run
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        slug: String,
+        /// Description of the event, e.g. "epoch 10"
+        #[arg(long, value_name = "TEXT")]
+        event: String,
+        /// Value logged with the event, can be given multiple times. Values are read as JSON
+        /// where possible, so loss=0.32 is logged as a number
+        #[arg(long = "meta", value_name = "KEY=VALUE", value_parser = parse_field)]
+        meta: Vec<(String, serde_json::Value)>,
+        /// Metadata file the run was recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// List recorded runs
+    List {
+        /// Only list runs with all of these tags
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Print runs in this format
+        #[arg(short, long, value_enum, value_name = "FORMAT")]
+        format: Option<metadata::Format>,
+        /// Metadata file the runs were recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Compare the metadata of two recorded runs
+    Diff {
+        /// Label or run id of the first run
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        a: String,
+        /// Label or run id of the second run
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        b: String,
+        /// Also compare fields that differ between any two runs, such as timestamps and notes
+        #[arg(long)]
+        all: bool,
+        /// Print the differences as a JSON array of {key, a, b} objects
+        #[arg(long)]
+        json: bool,
+        /// Metadata file the runs were recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Generate names and hold them in the metadata file for a while, so no other machine
+    /// generates them before the runs are recorded
+    Reserve {
+        /// How long the names are held, e.g. 30m, 12h or 7d
+        #[arg(long, value_name = "DURATION", default_value = "1h")]
+        ttl: String,
+        #[command(flatten)]
+        generate: GenerateArgs,
+    },
+    /// Give up a reserved name
+    Release {
+        /// Reserved name or its run id
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        slug: String,
+        /// Metadata file the name was reserved in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Record the metadata of a run named elsewhere, without generating a name
+    Register {
+        /// Names of the runs to record
+        #[arg(required = true, value_name = "NAME")]
+        names: Vec<String>,
+        /// Record the runs as running, like kioku start
+        #[arg(long)]
+        start: bool,
+        /// Record names that are recorded already once more
+        #[arg(long)]
+        force: bool,
+        #[command(flatten)]
+        generate: GenerateArgs,
+    },
+    /// Generate a new name for a run repeating a recorded one, taking over its tags and custom metadata
+    Clone {
+        /// Label or run id of the run to clone
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        slug: String,
+        /// Record the new run as running, like kioku start
+        #[arg(long)]
+        start: bool,
+        #[command(flatten)]
+        generate: GenerateArgs,
+    },
+    /// Render recorded runs into a standalone HTML page
+    Report {
+        /// HTML file to write
+        #[arg(long, value_name = "FILE", default_value = "report.html")]
+        out: PathBuf,
+        /// Only include runs matching all terms: text in the name, tag:TAG, status:STATUS, since:DATE, until:DATE
+        #[arg(long, value_name = "FILTER", default_value = "")]
+        filter: String,
+        /// Title of the page
+        #[arg(long, value_name = "TITLE", default_value = "Experiments")]
+        title: String,
+        /// Metadata file the runs were recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Browse recorded runs interactively, with filtering, tagging and notes
+    Tui {
+        /// Metadata file the runs were recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Map an identifier (number, hash or UUID) onto a name that decodes back to it
+    Encode {
+        /// Identifier made of decimal or hexadecimal digits and '-', hex decodes back bare and lowercase
+        id: String,
+        /// Wordlist to encode with, either the name of an installed wordlist or a path
+        #[arg(short, long, value_name = "WORDLIST")]
+        words: Option<String>,
+        /// Language of the default wordlist, used when --words is not given
+        #[arg(long, value_name = "LANG", env = "KIOKU_LANG")]
+        lang: Option<String>,
+        /// Separator placed between words
+        #[arg(short, long, value_name = "SEPARATOR", default_value = "-")]
+        separator: String,
+    },
+    /// Recover the identifier encoded in a name
+    Decode {
+        /// Name produced by kioku encode
+        name: String,
+        /// Wordlist the name was encoded with, either the name of an installed wordlist or a path
+        #[arg(short, long, value_name = "WORDLIST")]
+        words: Option<String>,
+        /// Language of the default wordlist, used when --words is not given
+        #[arg(long, value_name = "LANG", env = "KIOKU_LANG")]
+        lang: Option<String>,
+        /// Separator placed between words
+        #[arg(short, long, value_name = "SEPARATOR", default_value = "-")]
+        separator: String,
+    },
+    /// Import runs recorded by another tool, skipping runs already recorded
+    Import {
+        /// Tool or format the runs were recorded with
+        #[arg(value_enum)]
+        source: import::Source,
+        /// Tracking URI for mlflow, <ENTITY/PROJECT> for wandb, or the file to import
+        #[arg(value_name = "SOURCE")]
+        location: String,
+        /// MLflow experiment to import, defaults to $MLFLOW_EXPERIMENT_ID or 0
+        #[arg(long, value_name = "ID")]
+        experiment: Option<String>,
+        /// Metadata file to import the runs into
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Package recorded runs, with their notes, events and artifact checksums, into a bundle
+    /// for another machine
+    Export {
+        /// Labels or run ids of the runs to export
+        #[arg(required = true, add = ArgValueCandidates::new(complete_slugs))]
+        slugs: Vec<String>,
+        /// Bundle to write, a gzip compressed tarball
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+        /// Include the commits the runs were started at as patches, from the current repository
+        #[arg(long)]
+        patch: bool,
+        /// Metadata file the runs were recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Record the runs of a bundle written by kioku export, skipping runs already recorded
+    ImportBundle {
+        /// Bundle to read
+        bundle: PathBuf,
+        /// Extract the patches of the bundle into <DIR>
+        #[arg(long, value_name = "DIR")]
+        patches: Option<PathBuf>,
+        /// Metadata file to record the runs in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Check that a sealed JSON lines metadata file was not changed outside of kioku
+    VerifyLog {
+        /// Metadata file to check
+        file: PathBuf,
+        /// Hash of the last record reported by an earlier check, which must still be in the file
+        #[arg(long, value_name = "HASH")]
+        head: Option<String>,
+    },
+    /// Remove old runs from a metadata file, optionally moving them to an archive. Sealed files
+    /// only grow, their runs are marked as removed instead.
+    Prune {
+        /// Remove runs started longer ago than <AGE>, such as 36h, 90d, 8w or 2y
+        #[arg(long, value_name = "AGE")]
+        older_than: String,
+        /// Only remove runs with this status
+        #[arg(long, value_enum)]
+        status: Option<metadata::Status>,
+        /// Append the removed runs to <FILE> as JSON lines, gzip compressed if it ends in .gz
+        #[arg(long, value_name = "FILE")]
+        archive: Option<PathBuf>,
+        /// List the runs that would be removed without changing any file
+        #[arg(long)]
+        dry_run: bool,
+        /// Metadata file to prune
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Upgrade the records of a metadata file to the current schema
+    Migrate {
+        /// Metadata file to upgrade in place
+        file: PathBuf,
+    },
+    /// Print a script enabling tab completion in <SHELL>, including the labels of recorded runs
+    Completions {
+        /// Shell to complete in
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Serve an HTTP API for requesting names and querying recorded runs
+    Serve {
+        #[command(flatten)]
+        generate: GenerateArgs,
+        /// Port to listen on
+        #[arg(short, long, default_value = "7878")]
+        port: u16,
+        /// Address to listen on, use 0.0.0.0 to accept requests from other machines
+        #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1")]
+        bind: String,
+    },
+    /// Print the man page
+    #[command(hide = true)]
+    Mangen,
+}
+
+#[derive(Subcommand)]
+enum ArtifactCommands {
+    /// Record the size and SHA-256 checksum of files produced by a run
+    Add {
+        /// Label or run id of the run
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        slug: String,
+        /// Files to record, directories are recorded file by file
+        #[arg(required = true, value_name = "PATH")]
+        paths: Vec<PathBuf>,
+        /// Metadata file the run was recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+    /// Check the artifacts of a run against their recorded checksums
+    Verify {
+        /// Label or run id of the run
+        #[arg(add = ArgValueCandidates::new(complete_slugs))]
+        slug: String,
+        /// Metadata file the run was recorded in
+        #[arg(short, long, value_name = "FILE", env = "KIOKU_OUTPUT")]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WordlistCommands {
+    /// Install a wordlist from a file or URL under <NAME>
+    Add {
+        /// Name used to refer to the wordlist with --words, defaults to default-<LANG> with --lang
+        #[arg(required_unless_present = "lang")]
+        name: Option<String>,
+        /// Path or URL of the wordlist, defaults to $KIOKU_WORDLIST_URL or the upstream wordlist
+        #[arg(value_name = "PATH|URL")]
+        source: Option<String>,
+        /// Download the upstream wordlist of language <LANG>, which --lang then uses by default
+        #[arg(long, value_name = "LANG")]
+        lang: Option<String>,
+        /// Expected SHA-256 checksum of the wordlist, defaults to $KIOKU_WORDLIST_SHA256
+        #[arg(long, value_name = "HEX")]
+        sha256: Option<String>,
+        /// Allow downloading over plain HTTP
+        #[arg(long)]
+        allow_http: bool,
+    },
+    /// List installed wordlists
+    List,
+    /// Download installed wordlists again if their source changed, all downloaded ones by default
+    Update {
+        /// Wordlists to update
+        #[arg(add = ArgValueCandidates::new(complete_wordlists))]
+        names: Vec<String>,
+    },
+    /// Report the size of the namespace of a wordlist and the chance of name collisions
+    Stats {
+        /// Wordlist to analyse, either the name of an installed wordlist or a path
+        #[arg(short, long, value_name = "WORDLIST")]
+        words: Option<String>,
+        /// Length of the generated names in words [default: 3]
+        #[arg(short, long, value_name = "LENGTH")]
+        length: Option<usize>,
+        /// Number of names drawn for the collision estimate
+        #[arg(short, long, value_name = "COUNT", default_value = "10000")]
+        draws: u64,
+    },
+    /// Remove an installed wordlist
+    Remove {
+        /// Name of the wordlist to remove
+        #[arg(add = ArgValueCandidates::new(complete_wordlists))]
+        name: String,
+    },
+}
+
+/// Give up on finding distinct names in a batch after this many draws per name
+const MAX_ATTEMPTS_PER_NAME: usize = 100;
+
+fn apply_template(template: &str, name: &str) -> String {
+    let mut label = template.replace("{name}", name);
+    if label.contains("{date}") {
+        label = label.replace(
+            "{date}",
+            &chrono::Local::now().format("%Y-%m-%d").to_string(),
+        );
+    }
+    if label.contains("{revision}") {
+        let revision = metadata::git_revision().unwrap_or_default();
+        label = label.replace("{revision}", &revision[..revision.len().min(7)]);
+    }
+    label
 }
 
-#[derive(Debug)]
-enum KiokuErr {
-    BrokenPipe,
-    ApplicationErr(anyhow::Error),
-    IOErr(io::Error),
+/// Parse a `KEY=VALUE` field, reading the value as JSON and falling back to a string
+fn parse_field(field: &str) -> Result<(String, serde_json::Value), String> {
+    let (key, value) = field
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {}", field))?;
+    if key.is_empty() {
+        return Err(format!("missing key in {}", field));
+    }
+    let value = serde_json::from_str(value)
+        .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+    Ok((key.to_string(), value))
+}
+
+/// Ask for confirmation on stderr, defaulting to yes.
+/// Without an interactive stdin the question is declined unless `--yes` was given.
+fn confirm(cli: &Cli, question: &str) -> io::Result<bool> {
+    if cli.yes {
+        return Ok(true);
+    }
+    if cli.no_input || !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    eprint!("{} [Y/n] ", question);
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(answer.is_empty() || answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
 }
 
-impl From<anyhow::Error> for KiokuErr {
-    fn from(value: anyhow::Error) -> Self {
-        KiokuErr::ApplicationErr(value)
+/// Print large counts in scientific notation rather than with dozens of digits
+fn format_count(n: f64) -> String {
+    if n < 1e15 {
+        format!("{:.0}", n)
+    } else {
+        format!("{:.3e}", n)
     }
 }
 
-impl From<io::Error> for KiokuErr {
-    fn from(value: io::Error) -> Self {
-        match value.kind() {
-            io::ErrorKind::BrokenPipe => KiokuErr::BrokenPipe,
-            _ => KiokuErr::IOErr(value),
+fn artifact_main(config: &config::Config, command: &ArtifactCommands) -> Result<(), KiokuErr> {
+    match command {
+        ArtifactCommands::Add {
+            slug,
+            paths,
+            output,
+        } => {
+            let path = store_path(output.as_ref(), config)?;
+            let base = artifact::base_dir(&path);
+            let artifacts = artifact::collect(paths, base)?;
+            let count = artifacts.len();
+            store::update(&path, slug, |meta| {
+                meta.add_artifacts(artifacts);
+                Ok(())
+            })?;
+            log::info!("Recorded {} artifacts of {}", count, slug);
+        }
+        ArtifactCommands::Verify { slug, output } => {
+            let path = store_path(output.as_ref(), config)?;
+            let base = artifact::base_dir(&path);
+            let meta = store::find(&path, slug)?;
+            let mut changed = 0;
+            let mut stdout = io::stdout().lock();
+            for artifact in &meta.artifacts {
+                let state = match artifact::check(artifact, base)? {
+                    artifact::Check::Unchanged => "ok",
+                    artifact::Check::Modified => "modified",
+                    artifact::Check::Missing => "missing",
+                };
+                if state != "ok" {
+                    changed += 1;
+                }
+                writeln!(stdout, "{:8} {}", state, artifact.path)?;
+            }
+            if changed > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} of {} artifacts of {} changed",
+                    changed,
+                    meta.artifacts.len(),
+                    slug
+                )
+                .into());
+            }
         }
     }
+    Ok(())
 }
 
-impl fmt::Display for KiokuErr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            KiokuErr::BrokenPipe => Ok(()),
-            KiokuErr::ApplicationErr(e) => write!(f, "{:#}", e),
-            KiokuErr::IOErr(e) => e.fmt(f),
+fn wordlist_main(
+    cli: &Cli,
+    config: &config::Config,
+    command: &WordlistCommands,
+) -> Result<(), KiokuErr> {
+    match command {
+        WordlistCommands::Add {
+            name,
+            source,
+            lang,
+            sha256,
+            allow_http,
+        } => {
+            let name = match name {
+                Some(name) => name.clone(),
+                None => wordlist::default_name(lang.as_deref())?,
+            };
+            let name = &name;
+            if wordlist::is_installed(name)
+                && !confirm(
+                    cli,
+                    &format!("Wordlist {} already exists, overwrite?", name),
+                )?
+            {
+                return Err(KiokuErr::Declined(format!(
+                    "wordlist {} already exists, pass --yes to overwrite",
+                    name
+                )));
+            }
+            wordlist::add(
+                name,
+                source.as_deref(),
+                lang.as_deref(),
+                sha256.as_deref(),
+                *allow_http,
+                config,
+            )?
+        }
+        WordlistCommands::List => {
+            let mut stdout = io::stdout().lock();
+            for name in wordlist::list()? {
+                writeln!(stdout, "{}", name)?;
+            }
+        }
+        WordlistCommands::Update { names } => {
+            let all = names.is_empty();
+            let names = if all {
+                wordlist::list()?
+            } else {
+                names.clone()
+            };
+            let mut stdout = io::stdout().lock();
+            for name in names {
+                let status = match wordlist::update(&name, config)? {
+                    wordlist::Update::Updated => "updated",
+                    wordlist::Update::UpToDate => "up to date",
+                    // Skipped silently when updating everything
+                    wordlist::Update::NoOrigin if all => continue,
+                    wordlist::Update::NoOrigin => "installed from a file",
+                };
+                writeln!(stdout, "{}: {}", name, status)?;
+            }
         }
+        WordlistCommands::Stats {
+            words,
+            length,
+            draws,
+        } => {
+            let source = words.as_ref().or(config.wordlist.as_ref());
+            let words = match source {
+                Some(words) => wordlist::parse_wordlist(&wordlist::resolve(words))?,
+                None => wordlist::ensure_wordlist(config.lang.as_deref())?,
+            };
+            let length = length.or(config.length).unwrap_or(3);
+            let stats = wordlist::stats(source.map_or("default", String::as_str), &words, length)?;
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "Words:            {}", stats.words)?;
+            writeln!(stdout, "Duplicates:       {}", stats.duplicates)?;
+            writeln!(stdout, "Average length:   {:.2}", stats.average_length)?;
+            writeln!(stdout, "Name length:      {} words", length)?;
+            writeln!(
+                stdout,
+                "Namespace size:   {}",
+                format_count(stats.namespace)
+            )?;
+            writeln!(
+                stdout,
+                "Collision chance: {:.4}% after {} names",
+                100.0 * stats.collision_probability(*draws),
+                draws
+            )?;
+            writeln!(
+                stdout,
+                "Even odds after:  {} names",
+                format_count(stats.draws_for_even_odds().ceil())
+            )?;
+        }
+        WordlistCommands::Remove { name } => wordlist::remove(name)?,
     }
+    Ok(())
 }
 
-impl std::error::Error for KiokuErr {}
+fn migrate_main(file: &Path) -> Result<(), KiokuErr> {
+    let (migrated, records) = metadata::with_lock(file, || {
+        let (layout, mut records) = store::read_values(file)?;
+        if layout == store::Layout::Lines && seal::is_sealed(&records) {
+            // Sealed files are never rewritten, migrated records supersede the ones they replace
+            let before = seal::current(records);
+            let mut records = before.clone();
+            let mut migrated = 0;
+            for record in records.iter_mut() {
+                if metadata::migrate_record(record)? {
+                    migrated += 1;
+                }
+            }
+            if migrated > 0 {
+                seal::verify(file, None)?;
+                let changed: Vec<_> = records
+                    .iter()
+                    .zip(&before)
+                    .filter(|(after, before)| after != before)
+                    .map(|(after, _)| after.clone())
+                    .collect();
+                seal::append(file, &changed)?;
+            }
+            return Ok((migrated, records));
+        }
+        let mut migrated = 0;
+        for record in records.iter_mut() {
+            if metadata::migrate_record(record)? {
+                migrated += 1;
+            }
+        }
+        // Rewrite JSON lines files regardless, older versions wrote records spanning several lines
+        if migrated > 0 || layout == store::Layout::Lines {
+            store::write_values(file, layout, &records)?;
+        }
+        Ok((migrated, records))
+    })?;
+    log::info!(
+        "Migrated {} of {} records in {} to schema version {}",
+        migrated,
+        records.len(),
+        file.to_string_lossy(),
+        metadata::SCHEMA_VERSION
+    );
+    Ok(())
+}
 
-#[derive(serde::Serialize)]
-struct MetaData<'a> {
-    label: &'a str,
-    revision: Option<String>,
-    timestamp: String,
+fn load_wordlist(
+    args: &GenerateArgs,
+    config: &config::Config,
+) -> anyhow::Result<Vec<wordlist::Word>> {
+    let mut wordlist = if args.words.as_deref() == Some("-") {
+        if args.from_hash.as_deref() == Some("-") {
+            anyhow::bail!("--words - and --from-hash - cannot both read stdin");
+        }
+        log::debug!("Reading the wordlist from stdin");
+        wordlist::parse_stdin()
+    } else if let Some(words) = args.words.as_ref().or(config.wordlist.as_ref()) {
+        let path = wordlist::resolve(words);
+        log::debug!("Using wordlist {}", path.to_string_lossy());
+        wordlist::parse_wordlist(&path)?
+    } else {
+        log::debug!("Using the default wordlist");
+        wordlist::ensure_wordlist(
+            args.lang
+                .as_ref()
+                .or(config.lang.as_ref())
+                .map(String::as_str),
+        )?
+    };
+    if let Some(path) = args.exclude_file.as_ref().or(config.exclude_file.as_ref()) {
+        let excluded = wordlist::parse_wordlist(Path::new(path))?;
+        wordlist::exclude(
+            &mut wordlist,
+            excluded.iter().map(|word| word.text.as_str()),
+        );
+    }
+    if args.profanity_filter || config.profanity_filter == Some(true) {
+        wordlist::exclude(&mut wordlist, wordlist::profanity());
+    }
+    Ok(wordlist)
 }
 
-static WORDLIST: &str = include_str!("../assets/wordlist.txt");
+/// Generate distinct names other than those `taken` in rounds of parallel sampling, passing the
+/// names accepted in each round to `emit`
+fn generate_names(
+    args: &GenerateArgs,
+    config: &config::Config,
+    scope: &name::Scope,
+    taken: &HashSet<String>,
+    mut emit: impl FnMut(&[String]) -> anyhow::Result<()>,
+) -> anyhow::Result<Vec<String>> {
+    let wordlist;
+    let source = match args.style.or(config.style).unwrap_or_default() {
+        name::Generator::Words => {
+            wordlist = load_wordlist(args, config)?;
+            name::Source::Words(name::Sampler::new(&wordlist)?)
+        }
+        name::Generator::Phonetic => {
+            name::Source::Phonetic(args.syllables.or(config.syllables).unwrap_or(2))
+        }
+    };
+    let style = name::NameStyle {
+        case: args.case.or(config.case),
+        separator: args
+            .separator
+            .as_ref()
+            .or(config.separator.as_ref())
+            .map(|separator| separator.nfc().collect()),
+        suffix: args.suffix.or(config.suffix).unwrap_or_default(),
+    };
+    let length = args.length.or(config.length).unwrap_or(3);
+    let template = config.template.as_ref();
+    let filter_cmd = args.filter_cmd.as_ref().or(config.filter_cmd.as_ref());
+    let constraints = name::Constraints {
+        max_len: args.max_len.or(config.max_len),
+        charset: args.charset.or(config.charset),
+    };
+    constraints.check_scope(scope)?;
+    let seed = match &args.from_hash {
+        Some(path) => Some(name::content_digest(path)?),
+        None => None,
+    };
+    let seed = seed.unwrap_or_else(rand::random);
+    log::debug!(
+        "Seeding name generation with {}",
+        seed.iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    );
+    let mut generate = |mut counter: Option<name::Counter>| -> anyhow::Result<Vec<String>> {
+        let mut streams = name::Streams::new(seed, args.count);
+        let mut seen = taken.clone();
+        let mut names = Vec::with_capacity(args.count);
+        let mut attempts = 0;
+        while names.len() < args.count {
+            let round = names.len();
+            for candidate in streams.sample(&source, length, &style, args.count - names.len()) {
+                if attempts == MAX_ATTEMPTS_PER_NAME * args.count {
+                    return Err(KiokuErr::NamesExhausted {
+                        count: args.count,
+                        filtered: filter_cmd.is_some(),
+                    }
+                    .into());
+                }
+                attempts += 1;
+                let mut name = name::finish_name(candidate, &style, counter.as_ref())?;
+                if let Some(template) = template {
+                    name = apply_template(template, &name);
+                }
+                let label = scope.label(&name);
+                if !constraints.allows(&name, &label) {
+                    continue;
+                }
+                let name = label;
+                // Rejected names stay in `seen`, so the filter is asked about each name only once
+                if !seen.insert(name.clone()) {
+                    continue;
+                }
+                if let Some(filter_cmd) = filter_cmd
+                    && !run::filter_accepts(filter_cmd, &name)?
+                {
+                    continue;
+                }
+                if let Some(counter) = &mut counter {
+                    counter.advance()?;
+                }
+                names.push(name);
+                if names.len() == args.count {
+                    break;
+                }
+            }
+            emit(&names[round..])?;
+        }
+        Ok(names)
+    };
+    if style.suffix != name::Suffix::Counter {
+        return generate(None);
+    }
+    let path = name::Counter::path()?;
+    if args.dry_run {
+        return generate(Some(name::Counter::read(&path, true)?));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.to_string_lossy()))?;
+    }
+    // Held until all names are generated, so no other kioku process hands out the same values
+    metadata::with_lock(&path, || generate(Some(name::Counter::read(&path, false)?)))
+}
+
+/// Whether the names are all there is to output, so they can be printed while more are generated
+fn names_only(args: &GenerateArgs, config: &config::Config) -> bool {
+    matches!(
+        args.format.or(config.format).unwrap_or_default(),
+        metadata::Format::Plain
+    ) && !args.print_id
+        && !args.interactive
+        && !args.emit_meta
+        && !args.meta_stdin
+        && !args.dry_run
+        && args.output.is_none()
+        && config.output.is_none()
+        && args.mkdir.is_none()
+        && config.mkdir != Some(true)
+        && args.mlflow.is_none()
+        && args.wandb.is_none()
+        && args.git_record.or(config.git_record).is_none()
+        && args.notify_url.is_none()
+        && config.notify_url.is_none()
+        && config.pre_generate.is_none()
+        && config.post_generate.is_none()
+}
+
+/// Namespace and prefix of the names generated now
+fn scope(args: &GenerateArgs, config: &config::Config) -> anyhow::Result<name::Scope> {
+    let namespace = match &args.namespace {
+        Some(namespace) => Some(namespace.clone()),
+        None => config
+            .namespace
+            .as_deref()
+            .map(name::parse_namespace)
+            .transpose()
+            .map_err(anyhow::Error::msg)?,
+    };
+    Ok(name::Scope {
+        namespace,
+        prefix: args
+            .prefix
+            .as_ref()
+            .or(config.prefix.as_ref())
+            .and_then(name::Prefix::render),
+    })
+}
 
-fn wordlist_filter_map<'a>(word: &'a str, dowarn: &mut bool) -> Option<&'a str> {
-    let tw = word.trim();
-    if tw
-        .chars()
-        .all(|x| char::is_ascii_lowercase(&x) || char::is_ascii_uppercase(&x))
+/// Labels recorded in the namespace of `scope` in the local metadata file, which new names avoid
+fn namespace_taken(
+    args: &GenerateArgs,
+    config: &config::Config,
+    scope: &name::Scope,
+) -> anyhow::Result<HashSet<String>> {
+    let Some(namespace) = &scope.namespace else {
+        return Ok(HashSet::new());
+    };
+    let Some(path) = store_path(args.output.as_ref(), config)
+        .ok()
+        .filter(|path| path.exists())
+    else {
+        return Ok(HashSet::new());
+    };
+    Ok(store::read_records(&path)?
+        .into_iter()
+        .filter(|meta| meta.namespace.as_ref() == Some(namespace))
+        .flat_map(|meta| std::iter::once(meta.label).chain(meta.aliases))
+        .collect())
+}
+
+/// Print names as they are generated, without collecting metadata for them. Names printed
+/// before generation fails stay printed.
+fn stream_names(args: &GenerateArgs, config: &config::Config) -> Result<(), KiokuErr> {
+    let scope = scope(args, config)?;
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    generate_names(args, config, &scope, &HashSet::new(), |names| {
+        for name in names {
+            writeln!(stdout, "{}", name)?;
+        }
+        stdout.flush()?;
+        Ok(())
+    })
+    .map_err(|e| match e.downcast::<io::Error>() {
+        Ok(e) => KiokuErr::from(e),
+        Err(e) => KiokuErr::from(e),
+    })?;
+    Ok(())
+}
+
+/// Let the user pick the name among batches of candidates, each new batch avoiding the names
+/// shown before
+fn pick_name(
+    args: &GenerateArgs,
+    config: &config::Config,
+    scope: &name::Scope,
+    taken: &HashSet<String>,
+) -> anyhow::Result<Vec<String>> {
+    if args.count != 1 {
+        anyhow::bail!("--interactive picks a single name, --count is not supported");
+    }
+    if !io::stderr().is_terminal() {
+        anyhow::bail!("--interactive needs a terminal to show the candidates on stderr");
+    }
+    let mut candidates = args.clone();
+    candidates.count = args.candidates.or(config.candidates).unwrap_or(5);
+    if candidates.count == 0 {
+        anyhow::bail!("--candidates must be at least 1");
+    }
+    let mut shown = taken.clone();
+    let picked = tui::pick(|| {
+        let names = generate_names(&candidates, config, scope, &shown, |_| Ok(()))?;
+        shown.extend(names.iter().cloned());
+        Ok(names)
+    })?;
+    match picked {
+        Some(name) => Ok(vec![name]),
+        None => Err(KiokuErr::Declined("no name was picked".to_string()).into()),
+    }
+}
+
+/// Generate names along with the metadata recorded for them
+fn new_records(
+    args: &GenerateArgs,
+    config: &config::Config,
+) -> anyhow::Result<Vec<metadata::MetaData>> {
+    let scope = scope(args, config)?;
+    let taken = namespace_taken(args, config, &scope)?;
+    let names = if args.interactive {
+        pick_name(args, config, &scope, &taken)?
+    } else {
+        generate_names(args, config, &scope, &taken, |_| Ok(()))?
+    };
+    named_records(&names, &scope, args, config)
+}
+
+/// Clock writing the start and end times of runs
+fn clock(args: &GenerateArgs, config: &config::Config) -> anyhow::Result<metadata::Clock> {
+    metadata::Clock::new(
+        args.timestamp.or(config.timestamp),
+        args.time_format.clone().or(config.time_format.clone()),
+    )
+}
+
+/// The JSON object on stdin given by `--meta-stdin`
+fn read_meta_stdin(
+    args: &GenerateArgs,
+) -> anyhow::Result<serde_json::Map<String, serde_json::Value>> {
+    if args.from_hash.as_deref() == Some("-") || args.words.as_deref() == Some("-") {
+        anyhow::bail!("--meta-stdin cannot read stdin together with --from-hash - or --words -");
+    }
+    if io::stdin().is_terminal() {
+        log::warn!("Reading metadata from stdin, end it with Ctrl-D");
+    }
+    match serde_json::from_reader(io::stdin().lock())
+        .context("Failed to parse metadata on stdin")?
     {
-        Some(tw)
+        serde_json::Value::Object(fields) => Ok(fields),
+        _ => anyhow::bail!("Metadata on stdin must be a JSON object"),
+    }
+}
+
+/// Capture the metadata recorded for runs named `names`, which were generated in `scope`
+fn named_records(
+    names: &[String],
+    scope: &name::Scope,
+    args: &GenerateArgs,
+    config: &config::Config,
+) -> anyhow::Result<Vec<metadata::MetaData>> {
+    let mut records = metadata::collect(
+        names,
+        config.repositories.as_deref().unwrap_or_default(),
+        &clock(args, config)?,
+    );
+    let job = scheduler::current_job(
+        args.scheduler
+            .or(config.scheduler)
+            .unwrap_or(scheduler::Scheduler::Auto),
+    );
+    let container = if args.capture_container || config.capture_container == Some(true) {
+        container::current_container()
     } else {
-        if *dowarn {
-            eprintln!("Wordlist contains invalid words, discarding");
-            *dowarn = false;
-        }
         None
+    };
+    let fields = if args.meta_stdin {
+        read_meta_stdin(args)?
+    } else {
+        serde_json::Map::new()
+    };
+    for meta in records.iter_mut() {
+        meta.retag(&args.tags);
+        meta.job = job.clone();
+        meta.container = container.clone();
+        meta.namespace = scope.namespace.clone();
+        meta.prefix = scope.prefix.clone();
+        meta.merge_fields(&fields)?;
+    }
+    Ok(records)
+}
+
+/// How new records go into an existing local metadata file
+#[derive(Clone, Copy)]
+enum Recording {
+    Append,
+    /// Replace the reservations of the names, refusing names of recorded runs unless forced
+    Register {
+        force: bool,
+    },
+}
+
+/// Write the metadata of new runs to the output file and run directories.
+/// Returns the metadata files written, which later updates of the runs need to go to.
+fn save_records(
+    args: &GenerateArgs,
+    config: &config::Config,
+    records: &mut [metadata::MetaData],
+    recording: Recording,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let on_failure = config.hook_failure.unwrap_or_default();
+    hook::trigger(
+        hook::Hook::PreGenerate,
+        config.pre_generate.as_ref(),
+        records,
+        on_failure,
+    )?;
+    if let Some(uri) = &args.mlflow {
+        let uri = mlflow::tracking_uri(uri.as_deref().filter(|u| !u.is_empty()))?;
+        for meta in records.iter_mut() {
+            meta.mlflow = Some(mlflow::create_run(&uri, meta)?);
+        }
+    }
+    if let Some(target) = &args.wandb {
+        for meta in records.iter_mut() {
+            meta.wandb = Some(wandb::create_run(target, meta)?);
+        }
+    }
+    let mut files = Vec::new();
+    let latest = config.latest != Some(false);
+    if args.mkdir.is_some() || config.mkdir == Some(true) {
+        let root = PathBuf::from(
+            args.experiments_dir
+                .as_deref()
+                .or(config.experiments_dir.as_deref())
+                .unwrap_or("experiments"),
+        );
+        let skeleton = match &args.mkdir {
+            // KIOKU_MKDIR= asks for a bare run directory, like --mkdir alone
+            Some(skeleton) => skeleton.as_ref().filter(|s| !s.is_empty()),
+            None => config.skeleton.as_ref(),
+        };
+        for meta in records.iter_mut() {
+            let dir = experiment::create(&root, &meta.label, skeleton.map(Path::new))?;
+            meta.directory = Some(platform::portable(&dir));
+            let file = dir.join(experiment::METADATA_FILE);
+            sink::JsonFile(file.clone()).write(std::slice::from_ref(meta))?;
+            files.push(file);
+            if latest {
+                experiment::update_latest(&root, &meta.label, &dir)?;
+            }
+        }
+    }
+    if let Some(output) = args.output.as_ref().or(config.output.as_ref()) {
+        let mut sink = sink::open(output, config);
+        if args.sealed || config.sealed == Some(true) {
+            sink.seal()?;
+        }
+        match (recording, sink.file()) {
+            (Recording::Register { force }, Some(file)) if file.exists() => {
+                store::register(file, records, force)?
+            }
+            _ => sink.write(records)?,
+        }
+        if let Some(file) = sink.file() {
+            // Runs with a directory already have `latest` pointing at them, and a pointer next
+            // to a shared log only makes sense when asked for
+            if let Some(meta) = records
+                .last()
+                .filter(|meta| config.latest == Some(true) && meta.directory.is_none())
+            {
+                let dir = file.parent().unwrap_or(Path::new(""));
+                experiment::write_latest_pointer(dir, &meta.label, file)?;
+            }
+            files.push(file.to_path_buf());
+        }
+    }
+    if let Some(kind) = args.git_record.or(config.git_record) {
+        git::record(kind, records)?;
+    }
+    if let Some(url) = args.notify_url.as_ref().or(config.notify_url.as_ref()) {
+        // The runs are recorded already, a missed announcement should not fail them
+        if let Err(e) = notify::notify(
+            url,
+            &config.notify_headers.clone().unwrap_or_default(),
+            records,
+        ) {
+            log::warn!("{:#}", e);
+        }
     }
+    hook::trigger(
+        hook::Hook::PostGenerate,
+        config.post_generate.as_ref(),
+        records,
+        on_failure,
+    )?;
+    Ok(files)
 }
 
-fn parse_wordlist(filename: &std::path::PathBuf) -> anyhow::Result<Vec<String>> {
-    let mut dowarn = true;
-    Ok(
-        io::BufReader::new(fs::File::open(filename).with_context(|| {
+/// Describe on stderr what `save_records` would do, returning the metadata files it would write
+fn preview_records(
+    args: &GenerateArgs,
+    config: &config::Config,
+    records: &[metadata::MetaData],
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut stderr = io::stderr().lock();
+    if let Some(command) = &config.pre_generate {
+        writeln!(stderr, "Would run the pre_generate hook {}", command)?;
+    }
+    if let Some(uri) = &args.mlflow {
+        let uri = mlflow::tracking_uri(uri.as_deref().filter(|u| !u.is_empty()))?;
+        writeln!(
+            stderr,
+            "Would create {} MLflow runs at {}",
+            records.len(),
+            uri
+        )?;
+    }
+    if let Some(target) = &args.wandb {
+        writeln!(
+            stderr,
+            "Would create {} W&B runs in {}",
+            records.len(),
+            target
+        )?;
+    }
+    let mut files = Vec::new();
+    if args.mkdir.is_some() || config.mkdir == Some(true) {
+        let root = PathBuf::from(
+            args.experiments_dir
+                .as_deref()
+                .or(config.experiments_dir.as_deref())
+                .unwrap_or("experiments"),
+        );
+        for meta in records {
+            let dir = root.join(&meta.label);
+            if dir.exists() {
+                return Err(anyhow::anyhow!(
+                    "Experiment directory {} already exists",
+                    dir.to_string_lossy()
+                ));
+            }
+            writeln!(stderr, "Would create directory {}", dir.to_string_lossy())?;
+            files.push(dir.join(experiment::METADATA_FILE));
+        }
+    }
+    if let Some(output) = args.output.as_ref().or(config.output.as_ref()) {
+        match sink::open(output, config).file() {
+            Some(file) => {
+                writeln!(
+                    stderr,
+                    "Would record the runs in {}",
+                    file.to_string_lossy()
+                )?;
+                files.push(file.to_path_buf());
+            }
+            None => writeln!(stderr, "Would record the runs in {}", output)?,
+        }
+    }
+    if let Some(kind) = args.git_record.or(config.git_record) {
+        match kind {
+            git::GitRecord::Note => writeln!(stderr, "Would add the runs to the git note on HEAD")?,
+            git::GitRecord::Tag => {
+                for meta in records {
+                    writeln!(stderr, "Would tag HEAD as exp/{}", meta.label)?;
+                }
+            }
+        }
+    }
+    if let Some(url) = args.notify_url.as_ref().or(config.notify_url.as_ref()) {
+        writeln!(stderr, "Would notify {}", url)?;
+    }
+    if let Some(command) = &config.post_generate {
+        writeln!(stderr, "Would run the post_generate hook {}", command)?;
+    }
+    Ok(files)
+}
+
+/// Record new runs and print their names
+fn generate_main(
+    args: &GenerateArgs,
+    config: &config::Config,
+    mut records: Vec<metadata::MetaData>,
+    status: Option<metadata::Status>,
+    recording: Recording,
+) -> Result<(), KiokuErr> {
+    for meta in records.iter_mut() {
+        meta.status = status;
+    }
+    let files = if args.dry_run {
+        preview_records(args, config, &records)?
+    } else {
+        save_records(args, config, &mut records, recording)?
+    };
+    let output = args.output.as_ref().or(config.output.as_ref());
+    if status.is_some() && files.is_empty() && output.is_none() {
+        return Err(anyhow::anyhow!(
+            "No metadata file to record the run in, pass --output or set `output` in the configuration"
+        )
+        .into());
+    }
+    let format = args.format.or(config.format).unwrap_or_default();
+    if output.is_some_and(|output| output == sink::STDOUT) && !args.dry_run {
+        // The stdout sink printed the records already
+    } else if args.print_id && matches!(format, metadata::Format::Plain) {
+        let mut stdout = io::stdout().lock();
+        for meta in &records {
+            writeln!(
+                stdout,
+                "{}\t{}",
+                meta.label,
+                meta.run_id.as_deref().unwrap_or_default()
+            )?;
+        }
+    } else {
+        metadata::print(&mut io::stdout().lock(), format, &records)?;
+    }
+    if args.emit_meta || (args.dry_run && matches!(format, metadata::Format::Plain)) {
+        metadata::print(&mut io::stderr().lock(), metadata::Format::Json, &records)?;
+    }
+    Ok(())
+}
+
+/// The metadata file that subcommands updating existing records operate on
+fn store_path(output: Option<&String>, config: &config::Config) -> anyhow::Result<PathBuf> {
+    let output = output
+        .or(config.output.as_ref())
+        .context("No metadata file given, pass --output or set `output` in the configuration")?;
+    sink::open(output, config)
+        .file()
+        .map(Path::to_path_buf)
+        .with_context(|| {
             format!(
-                "Failed to read wordlist file {}",
-                filename.to_string_lossy()
+                "Metadata sink {} is append only, pass --output with a local file",
+                output
             )
-        })?)
-        .lines()
-        .filter_map(|x| x.ok())
-        .filter_map(|x| wordlist_filter_map(x.as_str(), &mut dowarn).map(|y| y.to_string()))
-        .collect(),
-    )
+        })
+}
+
+/// Labels of the runs in the configured metadata file, offered when completing a slug
+fn complete_slugs() -> Vec<CompletionCandidate> {
+    let Ok(config) = config::load() else {
+        return Vec::new();
+    };
+    store_path(None, &config)
+        .and_then(|path| store::read_records(&path))
+        .map(|records| {
+            records
+                .into_iter()
+                .map(|meta| CompletionCandidate::new(meta.label))
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
-fn ensure_wordlist() -> Vec<String> {
-    WORDLIST
-        .split_whitespace()
-        .filter_map(|x| wordlist_filter_map(x, &mut false).map(|y| y.to_string()))
+fn complete_wordlists() -> Vec<CompletionCandidate> {
+    wordlist::list()
+        .unwrap_or_default()
+        .into_iter()
+        .map(CompletionCandidate::new)
         .collect()
 }
 
-fn generate_name<'a>(wordlist: &'a [String], num_words: usize) -> String {
-    let mut rng = rand::rng();
-    let mut output = String::new();
-    for word in (0..num_words).map(|_| wordlist[rng.random_range(0..wordlist.len())].as_str()) {
-        if output.len() == 0 {
-            output.push_str(word);
-        } else {
-            output.push('-');
-            output.push_str(word);
-        }
+/// Print the registration script of the completion engine, which calls back into kioku so
+/// candidates such as run labels are always current
+fn completions_main(shell: clap_complete::Shell) -> Result<(), KiokuErr> {
+    let shells = clap_complete::env::Shells::builtins();
+    let completer = shells
+        .completer(&shell.to_string())
+        .with_context(|| format!("Completion is not supported for {}", shell))?;
+    let bin = env!("CARGO_BIN_NAME");
+    completer.write_registration("COMPLETE", bin, bin, bin, &mut io::stdout().lock())?;
+    Ok(())
+}
+
+/// Mirror the end of a run to the tracking servers it was registered with.
+/// Failures only warn, the run itself has already been recorded.
+fn sync_finished(meta: &metadata::MetaData) {
+    if let Some(run) = &meta.mlflow
+        && let Err(e) = mlflow::finish_run(run, meta)
+    {
+        log::warn!("{:#}", e);
     }
-    output
 }
 
-fn generate_metadata(filename: &str, slug: &str) -> anyhow::Result<()> {
-    let revision = git2::Repository::discover(".").ok().and_then(|rep| {
-        rep.head()
-            .ok()
-            .and_then(|head| head.target())
-            .map(|oid| oid.to_string())
-    });
-    let timestamp = chrono::Local::now().to_rfc3339();
-    let meta = MetaData {
-        label: slug,
-        revision,
-        timestamp,
-    };
-    let mut opener = fs::OpenOptions::new();
-    opener.create(true);
-    if filename.ends_with(".jsonl") {
-        opener.append(true);
+fn run_main(
+    generate: &GenerateArgs,
+    config: &config::Config,
+    stdout: Option<&String>,
+    stderr: Option<&String>,
+    command: &[String],
+) -> Result<(), KiokuErr> {
+    if generate.count != 1 {
+        return Err(
+            anyhow::anyhow!("kioku run names exactly one run, --count is not supported").into(),
+        );
+    }
+    let clock = clock(generate, config)?;
+    let mut records = new_records(generate, config)?;
+    let meta = &mut records[0];
+    let name = meta.label.clone();
+    meta.status = Some(metadata::Status::Running);
+    meta.stdout = stdout.map(|path| path.replace("{name}", &name));
+    meta.stderr = stderr.map(|path| path.replace("{name}", &name));
+    let (stdout, stderr) = (meta.stdout.clone(), meta.stderr.clone());
+    if generate.dry_run {
+        preview_records(generate, config, &records)?;
+        eprintln!("Would run {}", command.join(" "));
+        metadata::print(&mut io::stderr().lock(), metadata::Format::Json, &records)?;
+        return Ok(());
+    }
+    let files = save_records(generate, config, &mut records, Recording::Append)?;
+    let mut env = match &records[0].wandb {
+        Some(run) => wandb::environment(run, &records[0]),
+        None => Vec::new(),
+    };
+    if let Some(run_id) = &records[0].run_id {
+        env.push(("KIOKU_RUN_ID".to_string(), run_id.clone()));
+    }
+    if generate.emit_meta {
+        metadata::print(&mut io::stderr().lock(), metadata::Format::Json, &records)?;
+    } else if generate.print_id {
+        eprintln!(
+            "{}\t{}",
+            name,
+            records[0].run_id.as_deref().unwrap_or_default()
+        );
     } else {
-        opener.write(true).truncate(true);
+        eprintln!("{}", name);
     }
-    let fname = if filename.ends_with(".jsonl") || filename.ends_with(".json") {
-        filename.to_string()
+    let status = run::run(
+        command,
+        &name,
+        &env,
+        stdout.as_deref().map(Path::new),
+        stderr.as_deref().map(Path::new),
+    );
+    // A command that could not be started failed, rather than staying running forever
+    let (outcome, exit_code) = match &status {
+        Ok(status) if status.success() => (metadata::Status::Success, status.code()),
+        Ok(status) => (metadata::Status::Failed, status.code()),
+        Err(_) => (metadata::Status::Failed, None),
+    };
+    let finish = |meta: &mut metadata::MetaData| {
+        meta.finish(outcome, &clock);
+        meta.exit_code = exit_code;
+    };
+    finish(&mut records[0]);
+    for file in files {
+        store::update(&file, &name, |meta| {
+            finish(meta);
+            Ok(())
+        })?;
+    }
+    // Sinks without a file are append only, the finished record supersedes the one of the start
+    if let Some(output) = generate.output.as_ref().or(config.output.as_ref()) {
+        let mut sink = sink::open(output, config);
+        if sink.file().is_none() {
+            sink.write(&records)?;
+        }
+    }
+    sync_finished(&records[0]);
+    match status?.code() {
+        Some(0) => Ok(()),
+        Some(code) => Err(KiokuErr::Exit(code)),
+        // Terminated by a signal
+        None => Err(KiokuErr::Exit(1)),
+    }
+}
+
+/// Reserve new names in the metadata file at `path` for `ttl`
+fn reserve_records(
+    args: &GenerateArgs,
+    config: &config::Config,
+    path: &Path,
+    ttl: &str,
+) -> anyhow::Result<Vec<metadata::MetaData>> {
+    let until = chrono::Utc::now()
+        .checked_add_signed(prune::parse_age(ttl)?)
+        .with_context(|| format!("Reservation time {} is too long", ttl))?
+        .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true);
+    let scope = scope(args, config)?;
+    // The lock is not held while the user is picking
+    let picked = if args.interactive {
+        Some(pick_name(args, config, &scope, &HashSet::new())?)
     } else {
-        format!("{}.json", filename)
+        None
     };
-    let mut writer = io::BufWriter::new(
-        opener
-            .open(fname.as_str())
-            .with_context(|| format!("Failed to write metadata file {}", fname))?,
-    );
+    store::reserve(path, |taken| {
+        let names = match &picked {
+            Some(names) => {
+                if let Some(name) = names.iter().find(|name| taken.contains(*name)) {
+                    anyhow::bail!("{} was taken while picking, try again", name);
+                }
+                names.clone()
+            }
+            None => generate_names(args, config, &scope, taken, |_| Ok(()))?,
+        };
+        let mut records = named_records(&names, &scope, args, config)?;
+        for meta in records.iter_mut() {
+            meta.status = Some(metadata::Status::Reserved);
+            meta.reserved_until = Some(until.clone());
+        }
+        Ok(records)
+    })
+}
 
-    serde_json::to_writer_pretty(&mut writer, &meta).unwrap();
-    writer.write("\n".as_bytes()).unwrap();
+fn reserve_main(args: &GenerateArgs, config: &config::Config, ttl: &str) -> Result<(), KiokuErr> {
+    let path = store_path(args.output.as_ref(), config)?;
+    let format = args.format.or(config.format).unwrap_or_default();
+    if args.dry_run {
+        prune::parse_age(ttl)?;
+        let records = new_records(args, config)?;
+        eprintln!(
+            "Would reserve the names in {} for {}",
+            path.to_string_lossy(),
+            ttl
+        );
+        metadata::print(&mut io::stdout().lock(), format, &records)?;
+        return Ok(());
+    }
+    let records = reserve_records(args, config, &path, ttl)?;
+    metadata::print(&mut io::stdout().lock(), format, &records)?;
     Ok(())
 }
 
-fn inner_main() -> Result<(), KiokuErr> {
-    let cli = Cli::parse();
-    let filepath = cli.words;
-    let wordlist = if let Some(fpath) = filepath {
-        parse_wordlist(&fpath)?
+fn serve_main(
+    generate: &GenerateArgs,
+    config: &config::Config,
+    bind: &str,
+    port: u16,
+) -> Result<(), KiokuErr> {
+    if generate.meta_stdin {
+        return Err(anyhow::anyhow!("kioku serve does not support --meta-stdin").into());
+    }
+    if generate.interactive {
+        return Err(anyhow::anyhow!("kioku serve does not support --interactive").into());
+    }
+    if generate.dry_run {
+        return Err(anyhow::anyhow!("kioku serve does not support --dry-run").into());
+    }
+    let store = generate
+        .output
+        .as_ref()
+        .or(config.output.as_ref())
+        .and_then(|output| sink::open(output, config).file().map(Path::to_path_buf));
+    serve::serve(&format!("{}:{}", bind, port), store.as_deref(), |request| {
+        let mut args = generate.clone();
+        args.count = request.count.unwrap_or(1);
+        args.length = request.length.or(args.length);
+        args.tags.extend(request.tags);
+        if let Some(ttl) = &request.ttl {
+            let path = store.as_deref().context(
+                "Reservations need a local metadata file, start the server with --output",
+            )?;
+            return reserve_records(&args, config, path, ttl);
+        }
+        let mut records = new_records(&args, config)?;
+        save_records(&args, config, &mut records, Recording::Append)?;
+        Ok(records)
+    })?;
+    Ok(())
+}
+
+/// Record the runs of a bundle that are not recorded in `path` yet, by run id or label
+fn import_bundle_main(path: &Path, bundle: &Path, patches: Option<&Path>) -> Result<(), KiokuErr> {
+    let bundle = bundle::read(bundle)?;
+    let known = if path.exists() {
+        store::read_records(path)?
     } else {
-        ensure_wordlist()
+        Vec::new()
     };
-    let name = generate_name(&wordlist, cli.length);
-    writeln!(io::stdout(), "{}", name)?;
-    if let Some(timestamp) = cli.output {
-        generate_metadata(timestamp.as_str(), name.as_str())?;
+    let total = bundle.records.len();
+    let runs: Vec<_> = bundle
+        .records
+        .into_iter()
+        .filter(|meta| {
+            !known
+                .iter()
+                .any(|other| match (&meta.run_id, &other.run_id) {
+                    (Some(id), Some(other)) => id == other,
+                    _ => meta.label == other.label,
+                })
+        })
+        .collect();
+    store::append(path, &runs)?;
+    log::info!(
+        "Imported {} of {} runs into {}",
+        runs.len(),
+        total,
+        path.to_string_lossy()
+    );
+    match patches {
+        Some(dir) => {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {}", dir.to_string_lossy()))?;
+            for (name, patch) in &bundle.patches {
+                let file = dir.join(name);
+                if file.exists() {
+                    log::info!("Keeping existing patch {}", file.to_string_lossy());
+                    continue;
+                }
+                fs::write(&file, patch)
+                    .with_context(|| format!("Failed to write {}", file.to_string_lossy()))?;
+            }
+        }
+        None if !bundle.patches.is_empty() => log::info!(
+            "The bundle contains {} patches, pass --patches to extract them",
+            bundle.patches.len()
+        ),
+        None => {}
     }
     Ok(())
 }
 
-fn main() {
-    if let Err(e) = inner_main() {
-        match e {
-            KiokuErr::BrokenPipe => {
-                std::process::exit(141);
+fn prune_main(
+    cli: &Cli,
+    config: &config::Config,
+    path: &Path,
+    older_than: &str,
+    status: Option<metadata::Status>,
+    archive: Option<&PathBuf>,
+    dry_run: bool,
+) -> Result<(), KiokuErr> {
+    let cutoff = chrono::Local::now()
+        .checked_sub_signed(prune::parse_age(older_than)?)
+        .with_context(|| format!("Age {} is too long", older_than))?;
+    let clock = metadata::Clock::new(config.timestamp, config.time_format.clone())?;
+    let records = store::read(path)?.1;
+    let unreadable = prune::unreadable(&records, &clock);
+    if unreadable > 0 {
+        log::warn!(
+            "{} runs in {} have a timestamp that is neither RFC 3339 nor in time_format, these are kept",
+            unreadable,
+            path.to_string_lossy()
+        );
+    }
+    let (pruned, kept): (Vec<_>, Vec<_>) = records
+        .into_iter()
+        .partition(|record| prune::is_pruned(record, cutoff, status, &clock));
+    if dry_run {
+        let mut stdout = io::stdout().lock();
+        for record in &pruned {
+            writeln!(stdout, "{}", record["label"].as_str().unwrap_or_default())?;
+        }
+        return Ok(());
+    }
+    if pruned.is_empty() {
+        log::info!("No runs to prune in {}", path.to_string_lossy());
+        return Ok(());
+    }
+    let question = format!(
+        "Remove {} of {} runs from {}?",
+        pruned.len(),
+        pruned.len() + kept.len(),
+        path.to_string_lossy()
+    );
+    if !confirm(cli, &question)? {
+        return Err(KiokuErr::Declined(
+            "no runs pruned, pass --yes to prune without asking".to_string(),
+        ));
+    }
+    // Select again under the lock, runs may have been recorded while asking
+    let pruned = metadata::with_lock(path, || {
+        let (layout, records) = store::read(path)?;
+        let (pruned, kept): (Vec<_>, Vec<_>) = records
+            .iter()
+            .cloned()
+            .partition(|record| prune::is_pruned(record, cutoff, status, &clock));
+        if let Some(archive) = archive {
+            prune::archive(archive, &pruned)?;
+        }
+        store::save(path, layout, &records, &kept)?;
+        Ok(pruned)
+    })?;
+    log::info!(
+        "Pruned {} runs from {}",
+        pruned.len(),
+        path.to_string_lossy()
+    );
+    Ok(())
+}
+
+/// The distinct words of a wordlist in order, as used for encoding identifiers
+fn mnemonic_words(
+    words: Option<&String>,
+    lang: Option<&String>,
+    config: &config::Config,
+) -> anyhow::Result<Vec<String>> {
+    let wordlist = match words.or(config.wordlist.as_ref()) {
+        Some(words) => wordlist::parse_wordlist(&wordlist::resolve(words))?,
+        None => wordlist::ensure_wordlist(lang.or(config.lang.as_ref()).map(String::as_str))?,
+    };
+    let mut seen = HashSet::new();
+    Ok(wordlist
+        .into_iter()
+        .map(|word| word.text)
+        .filter(|word| seen.insert(word.to_lowercase()))
+        .collect())
+}
+
+fn inner_main(cli: &Cli) -> Result<(), KiokuErr> {
+    logging::init(cli.quiet, cli.verbose);
+    if cli.help_all {
+        help::print_all(
+            &mut Cli::command().name(env!("CARGO_BIN_NAME")),
+            &mut io::stdout().lock(),
+        )?;
+        return Ok(());
+    }
+    let config = config::load()?;
+    seal::set_key(
+        std::env::var("KIOKU_SEAL_KEY")
+            .ok()
+            .or(config.seal_key.clone()),
+    );
+    match &cli.command {
+        None if names_only(&cli.generate, &config) => stream_names(&cli.generate, &config),
+        None => generate_main(
+            &cli.generate,
+            &config,
+            new_records(&cli.generate, &config)?,
+            None,
+            Recording::Append,
+        ),
+        Some(Commands::Start { generate }) => generate_main(
+            generate,
+            &config,
+            new_records(generate, &config)?,
+            Some(metadata::Status::Running),
+            Recording::Append,
+        ),
+        Some(Commands::Reserve { ttl, generate }) => reserve_main(generate, &config, ttl),
+        Some(Commands::Release { slug, output }) => {
+            let meta = store::release(&store_path(output.as_ref(), &config)?, slug)?;
+            log::info!("Released {}", meta.label);
+            Ok(())
+        }
+        Some(Commands::Register {
+            names,
+            start,
+            force,
+            generate,
+        }) => {
+            // Refuse recorded names before any hook runs, the store checks again under its lock
+            if !force
+                && let Ok(path) = store_path(generate.output.as_ref(), &config)
+                && path.exists()
+            {
+                for name in names {
+                    if let Ok(meta) = store::find(&path, name)
+                        && meta.status != Some(metadata::Status::Reserved)
+                    {
+                        return Err(anyhow::anyhow!(
+                            "{} is already recorded in {}, pass --force to record it again",
+                            name,
+                            path.to_string_lossy()
+                        )
+                        .into());
+                    }
+                }
             }
-            e => {
-                eprintln!("{}", e);
-                std::process::exit(1);
+            // Registered names are recorded as given, recording a reserved name claims it
+            let records = named_records(names, &name::Scope::default(), generate, &config)?;
+            let status = start.then_some(metadata::Status::Running);
+            let recording = Recording::Register { force: *force };
+            generate_main(generate, &config, records, status, recording)
+        }
+        Some(Commands::Clone {
+            slug,
+            start,
+            generate,
+        }) => {
+            let source = store::find(&store_path(generate.output.as_ref(), &config)?, slug)?;
+            let mut records = new_records(generate, &config)?;
+            for meta in records.iter_mut() {
+                meta.inherit(&source);
+            }
+            let status = start.then_some(metadata::Status::Running);
+            generate_main(generate, &config, records, status, Recording::Append)
+        }
+        Some(Commands::Finish {
+            slug,
+            status,
+            note,
+            output,
+        }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            let clock = metadata::Clock::new(config.timestamp, config.time_format.clone())?;
+            let meta = store::update(&path, slug, |meta| {
+                meta.finish(*status, &clock);
+                if let Some(note) = note {
+                    meta.add_note(note);
+                }
+                Ok(())
+            })?;
+            sync_finished(&meta);
+            Ok(())
+        }
+        Some(Commands::Run {
+            generate,
+            stdout,
+            stderr,
+            command,
+        }) => run_main(generate, &config, stdout.as_ref(), stderr.as_ref(), command),
+        Some(Commands::Tag {
+            slug,
+            changes,
+            output,
+        }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            store::update(&path, slug, |meta| {
+                meta.retag(changes);
+                Ok(())
+            })?;
+            Ok(())
+        }
+        Some(Commands::Rename { slug, new, output }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            let meta = store::rename(&path, slug, new)?;
+            if let Some(directory) = &meta.directory {
+                log::warn!("The run directory {} keeps its name", directory);
             }
+            Ok(())
+        }
+        Some(Commands::Note {
+            slug,
+            message,
+            output,
+        }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            store::update(&path, slug, |meta| {
+                meta.add_note(message);
+                Ok(())
+            })?;
+            Ok(())
+        }
+        Some(Commands::Log {
+            slug,
+            event,
+            meta,
+            output,
+        }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            store::update(&path, slug, |record| {
+                record.add_event(event, meta.iter().cloned().collect());
+                Ok(())
+            })?;
+            Ok(())
+        }
+        Some(Commands::List {
+            tags,
+            format,
+            output,
+        }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            let records: Vec<_> = store::read_records(&path)?
+                .into_iter()
+                .filter(|meta| tags.iter().all(|tag| meta.tags.contains(tag)))
+                .collect();
+            metadata::print(
+                &mut io::stdout().lock(),
+                format.or(config.format).unwrap_or_default(),
+                &records,
+            )?;
+            Ok(())
+        }
+        Some(Commands::Diff {
+            a,
+            b,
+            all,
+            json,
+            output,
+        }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            let changes = diff::diff(&store::find(&path, a)?, &store::find(&path, b)?, *all)?;
+            let mut stdout = io::stdout().lock();
+            if *json {
+                serde_json::to_writer_pretty(&mut stdout, &changes).context("Failed to print")?;
+                writeln!(stdout)?;
+            } else {
+                diff::print(&mut stdout, &changes)?;
+            }
+            Ok(())
+        }
+        Some(Commands::Report {
+            out,
+            filter,
+            title,
+            output,
+        }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            let clock = metadata::Clock::new(config.timestamp, config.time_format.clone())?;
+            let records = store::read_records(&path)?;
+            let unreadable = records
+                .iter()
+                .filter(|meta| meta.start_date(&clock).is_none())
+                .count();
+            if unreadable > 0 && (filter.contains("since:") || filter.contains("until:")) {
+                log::warn!(
+                    "{} runs have a timestamp that is neither RFC 3339 nor in time_format, these match no dates",
+                    unreadable
+                );
+            }
+            let records: Vec<_> = records
+                .into_iter()
+                .filter(|meta| meta.matches(filter, &clock))
+                .collect();
+            let commit_url = config
+                .commit_url
+                .clone()
+                .or_else(report::commit_url_template);
+            let html = report::render(title, &records, commit_url.as_deref());
+            metadata::write_atomic(out, |writer| Ok(writer.write_all(html.as_bytes())?))?;
+            Ok(())
+        }
+        Some(Commands::Tui { output }) => {
+            let clock = metadata::Clock::new(config.timestamp, config.time_format.clone())?;
+            tui::run(&store_path(output.as_ref(), &config)?, &clock)?;
+            Ok(())
+        }
+        Some(Commands::Encode {
+            id,
+            words,
+            lang,
+            separator,
+        }) => {
+            let wordlist = mnemonic_words(words.as_ref(), lang.as_ref(), &config)?;
+            let wordlist: Vec<&str> = wordlist.iter().map(String::as_str).collect();
+            let name = mnemonic::encode(id, &wordlist)?;
+            writeln!(io::stdout(), "{}", name.join(separator))?;
+            Ok(())
+        }
+        Some(Commands::Decode {
+            name,
+            words,
+            lang,
+            separator,
+        }) => {
+            let wordlist = mnemonic_words(words.as_ref(), lang.as_ref(), &config)?;
+            let wordlist: Vec<&str> = wordlist.iter().map(String::as_str).collect();
+            let name: String = name.nfc().collect();
+            let name: Vec<&str> = name.split(separator.as_str()).collect();
+            writeln!(io::stdout(), "{}", mnemonic::decode(&name, &wordlist)?)?;
+            Ok(())
+        }
+        Some(Commands::Wordlist(command)) => wordlist_main(cli, &config, command),
+        Some(Commands::Artifact(command)) => artifact_main(&config, command),
+        Some(Commands::Import {
+            source,
+            location,
+            experiment,
+            output,
+        }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            // Runs are known by their label or, for runs exported by kioku, their run id
+            let mut known: HashSet<String> = HashSet::new();
+            if path.exists() {
+                for meta in store::read_records(&path)? {
+                    known.extend(meta.run_id);
+                    known.insert(meta.label);
+                }
+            }
+            let runs = import::import(*source, location, experiment.as_deref())?;
+            let total = runs.len();
+            let runs: Vec<_> = runs
+                .into_iter()
+                .filter(|meta| {
+                    let new = !known.contains(&meta.label)
+                        && meta.run_id.as_ref().is_none_or(|id| !known.contains(id));
+                    known.extend(meta.run_id.clone());
+                    known.insert(meta.label.clone());
+                    new
+                })
+                .collect();
+            store::append(&path, &runs)?;
+            log::info!(
+                "Imported {} of {} runs into {}",
+                runs.len(),
+                total,
+                path.to_string_lossy()
+            );
+            Ok(())
+        }
+        Some(Commands::Export {
+            slugs,
+            out,
+            patch,
+            output,
+        }) => {
+            let path = store_path(output.as_ref(), &config)?;
+            let mut records: Vec<metadata::MetaData> = Vec::new();
+            for slug in slugs {
+                let meta = store::find(&path, slug)?;
+                if !records.iter().any(|other| other.run_id == meta.run_id) {
+                    records.push(meta);
+                }
+            }
+            bundle::export(out, &records, *patch)?;
+            log::info!(
+                "Exported {} runs to {}",
+                records.len(),
+                out.to_string_lossy()
+            );
+            Ok(())
+        }
+        Some(Commands::ImportBundle {
+            bundle,
+            patches,
+            output,
+        }) => import_bundle_main(
+            &store_path(output.as_ref(), &config)?,
+            bundle,
+            patches.as_deref(),
+        ),
+        Some(Commands::VerifyLog { file, head }) => {
+            let verified = seal::verify(file, head.as_deref())
+                .with_context(|| format!("Failed to verify {}", file.to_string_lossy()))?;
+            if !verified.authenticated {
+                log::warn!("No seal key is configured, the seals are not authenticated");
+            }
+            log::info!(
+                "Verified {} records of {}",
+                verified.records,
+                file.to_string_lossy()
+            );
+            writeln!(io::stdout(), "{}", verified.head)?;
+            Ok(())
+        }
+        Some(Commands::Prune {
+            older_than,
+            status,
+            archive,
+            dry_run,
+            output,
+        }) => prune_main(
+            cli,
+            &config,
+            &store_path(output.as_ref(), &config)?,
+            older_than,
+            *status,
+            archive.as_ref(),
+            *dry_run,
+        ),
+        Some(Commands::Migrate { file }) => migrate_main(file),
+        Some(Commands::Completions { shell }) => completions_main(*shell),
+        Some(Commands::Serve {
+            generate,
+            port,
+            bind,
+        }) => serve_main(generate, &config, bind, *port),
+        Some(Commands::Mangen) => {
+            help::man(
+                Cli::command().name(env!("CARGO_BIN_NAME")),
+                &mut io::stdout().lock(),
+            )?;
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
+    let cli = Cli::parse();
+    if let Err(e) = inner_main(&cli) {
+        match e {
+            e if e.is_broken_pipe() => std::process::exit(error::BROKEN_PIPE),
+            KiokuErr::Exit(code) => std::process::exit(code),
+            e => std::process::exit(error::report(cli.error_format, &e)),
         }
     }
 }
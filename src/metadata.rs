@@ -0,0 +1,870 @@
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Version of the metadata record layout, bump when adding a migration to `migrate_record`
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// State of the run a record belongs to
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Running,
+    Success,
+    Failed,
+    /// Name held by `kioku reserve` until `reserved_until`
+    Reserved,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Note {
+    pub timestamp: String,
+    pub text: String,
+}
+
+/// Progress of a run logged with `kioku log`, such as a checkpoint
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Event {
+    pub timestamp: String,
+    pub event: String,
+    /// Values logged along with the event, such as metrics
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub meta: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct MetaData {
+    pub schema_version: u64,
+    pub label: String,
+    /// Globally unique UUIDv7 of the run, stable when labels collide or change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// Former labels of the run, from `kioku rename`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Namespace the label starts with, from `--namespace`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Prefix the label starts with after the namespace, from `--prefix`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    pub revision: Option<String>,
+    /// Revisions of submodules and additional repositories, by path
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub revisions: BTreeMap<String, String>,
+    pub timestamp: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Directory created for the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished: Option<String>,
+    /// End of the lease of a reserved name, RFC 3339 in UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved_until: Option<String>,
+    /// Seconds between `timestamp` and `finished`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// File the standard output of a wrapped command was captured to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    /// File the standard error of a wrapped command was captured to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<Event>,
+    /// SLURM or PBS job the run was started in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<crate::scheduler::Job>,
+    /// Output files recorded with `kioku artifact add`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<crate::artifact::Artifact>,
+    /// Container image the run was started in, with `--capture-container`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<crate::container::Container>,
+    /// Label of the run this run was cloned from with `kioku clone`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloned_from: Option<String>,
+    /// MLflow run created for the run with `--mlflow`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mlflow: Option<crate::mlflow::MlflowRun>,
+    /// W&B run created for the run with `--wandb`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wandb: Option<crate::wandb::WandbRun>,
+    /// Fields unknown to this version, kept as is when records are rewritten
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl MetaData {
+    /// Mark the run as finished now, computing its duration from the start timestamp
+    pub fn finish(&mut self, status: Status, clock: &Clock) {
+        let now = chrono::Utc::now();
+        self.duration_secs = clock
+            .parse(&self.timestamp)
+            .map(|start| (now.fixed_offset() - start).as_seconds_f64());
+        self.finished = Some(clock.now());
+        self.status = Some(status);
+    }
+
+    /// Apply tag changes of the form `+tag` (or just `tag`) to add and `-tag` to remove
+    pub fn retag(&mut self, changes: &[String]) {
+        for change in changes {
+            if let Some(tag) = change.strip_prefix('-') {
+                self.tags.retain(|t| t != tag);
+            } else {
+                let tag = change.strip_prefix('+').unwrap_or(change);
+                if !tag.is_empty() && !self.tags.iter().any(|t| t == tag) {
+                    self.tags.push(tag.to_string());
+                }
+            }
+        }
+    }
+
+    /// The day the run started on as YYYY-MM-DD, in the time zone it was recorded in, `None` if
+    /// `clock` cannot read its timestamp
+    pub fn start_date(&self, clock: &Clock) -> Option<String> {
+        clock
+            .parse(&self.timestamp)
+            .map(|start| start.format("%Y-%m-%d").to_string())
+    }
+
+    /// Whether the run passes a filter of whitespace separated terms, all of which must match.
+    /// Terms are `tag:TAG`, `status:STATUS`, `namespace:NAME`, `since:DATE`, `until:DATE` (dates
+    /// as YYYY-MM-DD) or text contained in the label. Runs whose timestamp `clock` cannot read
+    /// match no dates.
+    pub fn matches(&self, filter: &str, clock: &Clock) -> bool {
+        let date = self.start_date(clock);
+        let date = date.as_deref();
+        filter
+            .split_whitespace()
+            .all(|term| match term.split_once(':') {
+                Some(("tag", tag)) => self.tags.iter().any(|t| t == tag),
+                Some(("status", status)) => {
+                    let actual = match self.status {
+                        Some(Status::Running) => "running",
+                        Some(Status::Success) => "success",
+                        Some(Status::Failed) => "failed",
+                        Some(Status::Reserved) => "reserved",
+                        None => "none",
+                    };
+                    actual == status
+                }
+                Some(("namespace", namespace)) => self.namespace.as_deref() == Some(namespace),
+                Some(("since", since)) => date.is_some_and(|date| date >= since),
+                Some(("until", until)) => date.is_some_and(|date| date <= until),
+                _ => self.label.contains(term),
+            })
+    }
+
+    /// Whether the record reserves its name at `now`. Leases that cannot be read never expire.
+    pub fn holds_reservation(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.status == Some(Status::Reserved)
+            && self.reserved_until.as_ref().is_none_or(|until| {
+                chrono::DateTime::parse_from_rfc3339(until).map_or(true, |until| until > now)
+            })
+    }
+
+    /// Whether `id` is the label or the run id of this run
+    pub fn is(&self, id: &str) -> bool {
+        self.label == id || self.run_id.as_deref() == Some(id)
+    }
+
+    /// Change the label, keeping the current one as an alias
+    pub fn rename(&mut self, label: &str) {
+        let old = std::mem::replace(&mut self.label, label.to_string());
+        self.aliases.retain(|alias| alias != label);
+        if !self.aliases.contains(&old) {
+            self.aliases.push(old);
+        }
+    }
+
+    /// Merge custom fields into the record, nested objects are merged key by key.
+    /// Fields kioku records itself cannot be set this way.
+    pub fn merge_fields(
+        &mut self,
+        fields: &serde_json::Map<String, serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        let mut record = serde_json::to_value(&*self)?;
+        if let serde_json::Value::Object(record) = &mut record {
+            for (key, value) in fields {
+                record.entry(key).or_insert_with(|| value.clone());
+            }
+        }
+        // Fields that do not end up among the custom ones belong to kioku
+        let probe: MetaData = serde_json::from_value(record)
+            .context("Custom fields conflict with the fields recorded by kioku")?;
+        if let Some(key) = fields.keys().find(|key| {
+            !probe.extra.contains_key(*key)
+                || *key == crate::seal::FIELD
+                || *key == crate::seal::REMOVED
+        }) {
+            anyhow::bail!(
+                "{} is recorded by kioku and cannot be set as a custom field",
+                key
+            );
+        }
+        for (key, value) in fields {
+            merge_value(
+                self.extra.entry(key).or_insert(serde_json::Value::Null),
+                value,
+            );
+        }
+        Ok(())
+    }
+
+    /// Take over the tags and custom fields of `source`, for a new run repeating it
+    pub fn inherit(&mut self, source: &MetaData) {
+        self.retag(&source.tags);
+        for (key, value) in &source.extra {
+            if key == crate::seal::FIELD || key == crate::seal::REMOVED {
+                continue;
+            }
+            self.extra
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+        self.cloned_from = Some(source.label.clone());
+    }
+
+    /// Record artifacts, replacing earlier records of the same paths
+    pub fn add_artifacts(&mut self, artifacts: Vec<crate::artifact::Artifact>) {
+        for artifact in artifacts {
+            self.artifacts.retain(|a| a.path != artifact.path);
+            self.artifacts.push(artifact);
+        }
+    }
+
+    pub fn add_note(&mut self, text: &str) {
+        self.notes.push(Note {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+            text: text.to_string(),
+        });
+    }
+
+    pub fn add_event(&mut self, event: &str, meta: serde_json::Map<String, serde_json::Value>) {
+        self.events.push(Event {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+            event: event.to_string(),
+            meta,
+        });
+    }
+}
+
+/// Merge `value` into `into`, recursing into objects present in both
+fn merge_value(into: &mut serde_json::Value, value: &serde_json::Value) {
+    match (into, value) {
+        (serde_json::Value::Object(into), serde_json::Value::Object(value)) => {
+            for (key, value) in value {
+                merge_value(into.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (into, value) => *into = value.clone(),
+    }
+}
+
+/// Time zone timestamps are recorded in
+#[derive(Clone, Copy, Default, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Zone {
+    /// Coordinated universal time
+    #[default]
+    Utc,
+    /// The time zone of the machine
+    Local,
+}
+
+/// How the start and end times of runs are written, RFC 3339 in UTC unless configured otherwise
+#[derive(Clone, Default)]
+pub struct Clock {
+    pub zone: Zone,
+    /// strftime format replacing RFC 3339
+    pub format: Option<String>,
+}
+
+impl Clock {
+    pub fn new(zone: Option<Zone>, format: Option<String>) -> anyhow::Result<Clock> {
+        if let Some(format) = &format
+            && chrono::format::StrftimeItems::new(format)
+                .any(|item| matches!(item, chrono::format::Item::Error))
+        {
+            anyhow::bail!("Invalid time format {}", format);
+        }
+        Ok(Clock {
+            zone: zone.unwrap_or_default(),
+            format,
+        })
+    }
+
+    fn write<Tz: chrono::TimeZone>(&self, time: chrono::DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        match &self.format {
+            Some(format) => time.format(format).to_string(),
+            None => time.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+        }
+    }
+
+    /// The current time
+    pub fn now(&self) -> String {
+        let now = chrono::Utc::now();
+        match self.zone {
+            Zone::Utc => self.write(now),
+            Zone::Local => self.write(now.with_timezone(&chrono::Local)),
+        }
+    }
+
+    /// Read a time written by this clock or as RFC 3339
+    pub fn parse(&self, time: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        if let Ok(time) = chrono::DateTime::parse_from_rfc3339(time) {
+            return Some(time);
+        }
+        let format = self.format.as_deref()?;
+        if let Ok(time) = chrono::DateTime::parse_from_str(time, format) {
+            return Some(time);
+        }
+        let naive = chrono::NaiveDateTime::parse_from_str(time, format).ok()?;
+        match self.zone {
+            Zone::Utc => Some(naive.and_utc().fixed_offset()),
+            Zone::Local => naive
+                .and_local_timezone(chrono::Local)
+                .earliest()
+                .map(|time| time.fixed_offset()),
+        }
+    }
+}
+
+/// Format used to print generated names on stdout
+#[derive(Clone, Copy, Default, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Only the name
+    #[default]
+    Plain,
+    /// One JSON record per line
+    Json,
+    /// A YAML document per record
+    Yaml,
+    /// Tab separated values with a header row
+    Tsv,
+}
+
+pub fn git_revision() -> Option<String> {
+    git2::Repository::discover(".")
+        .ok()
+        .and_then(|rep| head_id(&rep))
+}
+
+fn head_id(repo: &git2::Repository) -> Option<String> {
+    repo.head()
+        .ok()
+        .and_then(|head| head.target())
+        .map(|oid| oid.to_string())
+}
+
+/// Revisions checked out in the submodules of the current repository and in `repositories`
+pub fn other_revisions(repositories: &[String]) -> BTreeMap<String, String> {
+    let mut revisions = BTreeMap::new();
+    if let Ok(repo) = git2::Repository::discover(".")
+        && let Ok(submodules) = repo.submodules()
+    {
+        for submodule in submodules {
+            if let Some(oid) = submodule.workdir_id().or(submodule.head_id()) {
+                revisions.insert(
+                    submodule.path().to_string_lossy().into_owned(),
+                    oid.to_string(),
+                );
+            }
+        }
+    }
+    for path in repositories {
+        match git2::Repository::open(path) {
+            Ok(repo) => {
+                if let Some(oid) = head_id(&repo) {
+                    revisions.insert(path.clone(), oid);
+                }
+            }
+            Err(e) => log::warn!("Failed to read the revision of repository {}: {}", path, e),
+        }
+    }
+    revisions
+}
+
+/// Format a UUIDv7 from milliseconds since the epoch and 74 of the `random` bits
+fn uuid_v7(millis: u64, random: u128) -> String {
+    let id = ((millis as u128 & ((1 << 48) - 1)) << 80)
+        | (0x7 << 76)
+        | ((random >> 64) & 0xfff) << 64
+        | (0b10 << 62)
+        | (random & ((1 << 62) - 1));
+    let hex = format!("{:032x}", id);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// A new UUIDv7, which sorts by creation time
+pub fn new_run_id() -> String {
+    uuid_v7(chrono::Utc::now().timestamp_millis() as u64, rand::random())
+}
+
+/// Run id for a record written before run ids existed, derived from its label and start time so
+/// it stays the same whenever the record is read
+fn legacy_run_id(label: &str, timestamp: &str) -> String {
+    let millis = chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| time.timestamp_millis().max(0) as u64)
+        .unwrap_or(0);
+    let digest = <sha2::Sha256 as sha2::Digest>::digest(format!("{}\n{}", label, timestamp));
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid_v7(millis, u128::from_le_bytes(bytes))
+}
+
+/// Capture the metadata of freshly generated names, including the revisions of `repositories`
+pub fn collect(slugs: &[String], repositories: &[String], clock: &Clock) -> Vec<MetaData> {
+    let revision = git_revision();
+    let revisions = other_revisions(repositories);
+    let timestamp = clock.now();
+    slugs
+        .iter()
+        .map(|slug| MetaData {
+            schema_version: SCHEMA_VERSION,
+            label: slug.clone(),
+            run_id: Some(new_run_id()),
+            aliases: Vec::new(),
+            namespace: None,
+            prefix: None,
+            revision: revision.clone(),
+            revisions: revisions.clone(),
+            timestamp: timestamp.clone(),
+            tags: Vec::new(),
+            directory: None,
+            status: None,
+            finished: None,
+            reserved_until: None,
+            duration_secs: None,
+            exit_code: None,
+            stdout: None,
+            stderr: None,
+            notes: Vec::new(),
+            events: Vec::new(),
+            job: None,
+            container: None,
+            artifacts: Vec::new(),
+            cloned_from: None,
+            mlflow: None,
+            wandb: None,
+            extra: serde_json::Map::new(),
+        })
+        .collect()
+}
+
+/// Upgrade a record written by an older version to the current schema.
+/// Returns whether the record was changed.
+pub fn migrate_record(record: &mut serde_json::Value) -> anyhow::Result<bool> {
+    let Some(fields) = record.as_object_mut() else {
+        anyhow::bail!("Metadata record is not a JSON object");
+    };
+    let version = fields
+        .get("schema_version")
+        .and_then(serde_json::Value::as_u64)
+        .unwrap_or(0);
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "Metadata record has schema version {}, newer than supported version {}",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(false);
+    }
+    // Version 0 records only lack the version field itself, version 1 records the run id
+    let mut run_id = match (fields.get("label"), fields.get("timestamp")) {
+        (Some(serde_json::Value::String(label)), Some(serde_json::Value::String(timestamp)))
+            if !fields.contains_key("run_id") =>
+        {
+            Some(legacy_run_id(label, timestamp))
+        }
+        _ => None,
+    };
+    let mut migrated = serde_json::Map::with_capacity(fields.len() + 2);
+    migrated.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+    for (key, value) in std::mem::take(fields) {
+        if key == "schema_version" {
+            continue;
+        }
+        let is_label = key == "label";
+        migrated.insert(key, value);
+        if is_label && let Some(run_id) = run_id.take() {
+            migrated.insert("run_id".to_string(), run_id.into());
+        }
+    }
+    *fields = migrated;
+    Ok(true)
+}
+
+pub fn write_records(
+    writer: &mut impl Write,
+    records: &[MetaData],
+    jsonl: bool,
+) -> anyhow::Result<()> {
+    if jsonl {
+        for meta in records {
+            serde_json::to_writer(&mut *writer, meta)?;
+            writer.write_all("\n".as_bytes())?;
+        }
+    } else if let [meta] = records {
+        serde_json::to_writer_pretty(&mut *writer, meta)?;
+        writer.write_all("\n".as_bytes())?;
+    } else {
+        serde_json::to_writer_pretty(&mut *writer, &records)?;
+        writer.write_all("\n".as_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Replace `path` with the output of `write`, going through a temporary file in the same
+/// directory so readers never observe a partially written file
+pub fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut io::BufWriter<fs::File>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Invalid file name {}", path.to_string_lossy()))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+    let result = fs::File::create(&tmp_path)
+        .with_context(|| format!("Failed to create file {}", tmp_path.to_string_lossy()))
+        .and_then(|file| {
+            let mut writer = io::BufWriter::new(file);
+            write(&mut writer)?;
+            writer.get_ref().sync_all()?;
+            Ok(())
+        })
+        .and_then(|_| {
+            fs::rename(&tmp_path, path)
+                .with_context(|| format!("Failed to replace file {}", path.to_string_lossy()))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Run `f` holding an exclusive lock on `path`, so concurrent kioku processes appending to or
+/// rewriting the same metadata file take turns. The lock is taken on a `.<name>.lock` file next
+/// to `path`, as atomic replacement swaps out the metadata file itself.
+pub fn with_lock<T>(path: &Path, f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Invalid file name {}", path.to_string_lossy()))?;
+    let mut lock_name = OsString::from(".");
+    lock_name.push(file_name);
+    lock_name.push(".lock");
+    let lock_path = path.with_file_name(lock_name);
+    let lock = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to create lock file {}", lock_path.to_string_lossy()))?;
+    let locked = match lock.try_lock() {
+        Ok(()) => Ok(()),
+        Err(fs::TryLockError::WouldBlock) => {
+            log::debug!("Waiting for lock on {}", path.to_string_lossy());
+            lock.lock()
+        }
+        Err(fs::TryLockError::Error(e)) => Err(e),
+    };
+    locked.with_context(|| format!("Failed to lock {}", lock_path.to_string_lossy()))?;
+    // Released when `lock` is closed
+    f()
+}
+
+/// Flatten a record into its top level fields
+fn fields(meta: &MetaData) -> serde_json::Result<serde_json::Map<String, serde_json::Value>> {
+    match serde_json::to_value(meta)? {
+        serde_json::Value::Object(fields) => Ok(fields),
+        _ => Ok(serde_json::Map::new()),
+    }
+}
+
+fn field_string(value: Option<&serde_json::Value>) -> String {
+    match value {
+        None | Some(serde_json::Value::Null) => String::new(),
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn write_csv_rows(
+    writer: &mut csv::Writer<impl Write>,
+    header: &[String],
+    rows: &[serde_json::Map<String, serde_json::Value>],
+) -> anyhow::Result<()> {
+    for fields in rows {
+        writer.write_record(header.iter().map(|k| field_string(fields.get(k))))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Append rows to a CSV file, writing the header row when the file is created.
+/// Rows follow the column order of an existing header. When the records have fields the header
+/// lacks, the file is rewritten with these columns added, so the caller must hold its lock.
+pub fn append_csv(path: &Path, records: &[MetaData]) -> anyhow::Result<()> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    let rows = records
+        .iter()
+        .map(fields)
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    let existing = fs::metadata(path).is_ok_and(|m| m.len() > 0);
+    let mut header: Vec<String> = if existing {
+        csv::Reader::from_path(path)?
+            .headers()?
+            .iter()
+            .map(str::to_string)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let known = header.len();
+    for fields in &rows {
+        for key in fields.keys() {
+            if !header.contains(key) {
+                header.push(key.clone());
+            }
+        }
+    }
+    if !existing || header.len() == known {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let mut writer = csv::Writer::from_writer(file);
+        if !existing {
+            writer.write_record(&header)?;
+        }
+        return write_csv_rows(&mut writer, &header, &rows);
+    }
+    log::debug!(
+        "Adding columns to the CSV header of {}",
+        path.to_string_lossy()
+    );
+    let old_rows = csv::Reader::from_path(path)?
+        .into_records()
+        .collect::<Result<Vec<_>, _>>()?;
+    write_atomic(path, |file| {
+        let mut writer = csv::Writer::from_writer(file);
+        writer.write_record(&header)?;
+        for row in &old_rows {
+            writer.write_record(
+                row.iter()
+                    .chain(std::iter::repeat_n("", header.len() - known)),
+            )?;
+        }
+        write_csv_rows(&mut writer, &header, &rows)
+    })
+}
+
+/// Print the generated records to `out` in the requested format
+pub fn print(out: &mut impl Write, format: Format, records: &[MetaData]) -> io::Result<()> {
+    match format {
+        Format::Plain => {
+            for meta in records {
+                writeln!(out, "{}", meta.label)?;
+            }
+        }
+        Format::Json => {
+            for meta in records {
+                serde_json::to_writer(&mut *out, meta)?;
+                writeln!(out)?;
+            }
+        }
+        Format::Yaml => {
+            for meta in records {
+                writeln!(out, "---")?;
+                serde_yaml_ng::to_writer(&mut *out, meta).map_err(io::Error::other)?;
+            }
+        }
+        Format::Tsv => {
+            for (i, meta) in records.iter().enumerate() {
+                let fields = fields(meta)?;
+                if i == 0 {
+                    let header: Vec<&str> = fields.keys().map(String::as_str).collect();
+                    writeln!(out, "{}", header.join("\t"))?;
+                }
+                let row: Vec<String> = fields
+                    .values()
+                    .map(|v| field_string(Some(v)).replace(['\t', '\n'], " "))
+                    .collect();
+                writeln!(out, "{}", row.join("\t"))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_adds_stable_run_ids() {
+        let old = serde_json::json!({
+            "schema_version": 1,
+            "label": "gene-ruin-note",
+            "revision": null,
+            "timestamp": "2026-01-15T07:40:09+00:00",
+        });
+        let mut first = old.clone();
+        let mut second = old.clone();
+        assert!(migrate_record(&mut first).unwrap());
+        migrate_record(&mut second).unwrap();
+        assert_eq!(first, second);
+        let keys: Vec<&String> = first.as_object().unwrap().keys().collect();
+        assert_eq!(keys[..3], ["schema_version", "label", "run_id"]);
+        let run_id = first["run_id"].as_str().unwrap();
+        // Starts with the start time, 0x19bc098f7a8 ms since the epoch, and version 7
+        assert!(run_id.starts_with("019bc098-f7a8-7"), "{}", run_id);
+        assert!(!migrate_record(&mut first).unwrap());
+    }
+
+    #[test]
+    fn clock_formats() {
+        let utc = Clock::default().now();
+        assert!(utc.ends_with('Z'), "{}", utc);
+        let clock = Clock::new(Some(Zone::Utc), Some("%Y-%m-%d %H:%M:%S".to_string())).unwrap();
+        let start = clock.parse("2026-01-15 07:40:09").unwrap();
+        assert_eq!(start.to_rfc3339(), "2026-01-15T07:40:09+00:00");
+        assert!(clock.parse(&utc).is_some());
+        assert!(Clock::new(None, Some("%Q".to_string())).is_err());
+    }
+
+    #[test]
+    fn filter_terms() {
+        let mut meta = collect(&["gene-ruin-note".to_string()], &[], &Clock::default()).remove(0);
+        meta.timestamp = "2026-01-15T07:40:09+00:00".to_string();
+        meta.tags = vec!["baseline".to_string()];
+        meta.status = Some(Status::Failed);
+        let clock = Clock::default();
+        assert!(meta.matches("", &clock));
+        assert!(meta.matches("ruin tag:baseline status:failed", &clock));
+        assert!(meta.matches("since:2026-01-15 until:2026-01-15", &clock));
+        assert!(!meta.matches("since:2026-01-16", &clock));
+        assert!(!meta.matches("tag:other", &clock));
+        assert!(!meta.matches("status:running", &clock));
+        assert!(!meta.matches("gene ruin-x", &clock));
+
+        meta.timestamp = "15.01.2026 07:40".to_string();
+        assert!(!meta.matches("since:2026-01-01", &clock));
+        assert!(!meta.matches("until:2026-12-31", &clock));
+        let custom = Clock::new(None, Some("%d.%m.%Y %H:%M".to_string())).unwrap();
+        assert!(meta.matches("since:2026-01-15 until:2026-01-15", &custom));
+        assert!(!meta.matches("until:2026-01-14", &custom));
+    }
+
+    #[test]
+    fn merged_fields_nest() {
+        let mut meta = collect(&["gene-ruin-note".to_string()], &[], &Clock::default()).remove(0);
+        let fields = |value: serde_json::Value| value.as_object().unwrap().clone();
+        meta.merge_fields(&fields(
+            serde_json::json!({"model": {"depth": 3, "act": "relu"}}),
+        ))
+        .unwrap();
+        meta.merge_fields(&fields(
+            serde_json::json!({"model": {"depth": 4}, "lr": 0.1}),
+        ))
+        .unwrap();
+        assert_eq!(
+            serde_json::Value::Object(meta.extra.clone()),
+            serde_json::json!({"model": {"depth": 4, "act": "relu"}, "lr": 0.1})
+        );
+        assert!(
+            meta.merge_fields(&fields(serde_json::json!({"label": "x"})))
+                .is_err()
+        );
+        assert!(
+            meta.merge_fields(&fields(serde_json::json!({"status": "running"})))
+                .is_err()
+        );
+        assert_eq!(meta.label, "gene-ruin-note");
+    }
+
+    #[test]
+    fn clones_inherit_custom_fields_but_not_seals() {
+        let clock = Clock::default();
+        let mut source = collect(&["gene-ruin-note".to_string()], &[], &clock).remove(0);
+        source.tags = vec!["baseline".to_string()];
+        source.extra.insert("lr".to_string(), 0.1.into());
+        source.extra.insert(
+            crate::seal::FIELD.to_string(),
+            serde_json::json!({"seq": 0}),
+        );
+        let mut clone = collect(&["robe-speed-fake".to_string()], &[], &clock).remove(0);
+        clone.inherit(&source);
+        assert_eq!(clone.tags, ["baseline"]);
+        assert_eq!(clone.extra["lr"], 0.1);
+        assert!(!clone.extra.contains_key(crate::seal::FIELD));
+        assert_eq!(clone.cloned_from.as_deref(), Some("gene-ruin-note"));
+    }
+
+    #[test]
+    fn csv_header_grows_with_new_fields() {
+        let path = std::env::temp_dir().join(format!("kioku-test-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let clock = Clock::default();
+        append_csv(
+            &path,
+            &collect(&["gene-ruin-note".to_string()], &[], &clock),
+        )
+        .unwrap();
+        let mut started = collect(&["robe-speed-fake".to_string()], &[], &clock);
+        started[0].status = Some(Status::Running);
+        started[0].tags = vec!["exp1".to_string()];
+        append_csv(&path, &started).unwrap();
+        append_csv(
+            &path,
+            &collect(&["wedge-sash-fund".to_string()], &[], &clock),
+        )
+        .unwrap();
+
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let header = reader.headers().unwrap().clone();
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        fs::remove_file(&path).unwrap();
+        let column = |name: &str| header.iter().position(|k| k == name).unwrap();
+        let labels: Vec<&str> = rows.iter().map(|row| &row[column("label")]).collect();
+        assert_eq!(
+            labels,
+            ["gene-ruin-note", "robe-speed-fake", "wedge-sash-fund"]
+        );
+        assert_eq!(&rows[0][column("status")], "");
+        assert_eq!(&rows[1][column("status")], "running");
+        assert_eq!(&rows[1][column("tags")], "[\"exp1\"]");
+        assert_eq!(&rows[2][column("tags")], "");
+    }
+}
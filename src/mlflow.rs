@@ -0,0 +1,103 @@
+use crate::metadata::{MetaData, Status};
+use anyhow::Context;
+use serde_json::json;
+use std::env;
+
+/// The MLflow run mirroring a kioku run
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct MlflowRun {
+    pub tracking_uri: String,
+    pub run_id: String,
+}
+
+/// Tracking server to use, `MLFLOW_TRACKING_URI` is used when none is given
+pub fn tracking_uri(uri: Option<&str>) -> anyhow::Result<String> {
+    uri.map(str::to_string)
+        .or_else(|| env::var("MLFLOW_TRACKING_URI").ok())
+        .context("No MLflow tracking server given, pass --mlflow <URI> or set MLFLOW_TRACKING_URI")
+}
+
+/// Send a request to the MLflow REST API, authenticating like the MLflow client does
+fn post(
+    tracking_uri: &str,
+    endpoint: &str,
+    body: &serde_json::Value,
+) -> anyhow::Result<serde_json::Value> {
+    let url = format!(
+        "{}/api/2.0/mlflow/{}",
+        tracking_uri.trim_end_matches('/'),
+        endpoint
+    );
+    let mut request = ureq::post(&url).header("Content-Type", "application/json");
+    if let Ok(token) = env::var("MLFLOW_TRACKING_TOKEN") {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let response = request
+        .send(body.to_string())
+        .and_then(|mut resp| resp.body_mut().read_to_string())
+        .with_context(|| format!("MLflow request to {} failed", url))?;
+    serde_json::from_str(&response)
+        .with_context(|| format!("Failed to parse MLflow response from {}", url))
+}
+
+fn millis(timestamp: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|time| time.timestamp_millis())
+}
+
+/// Create an MLflow run named after the record, with its metadata as run tags
+pub fn create_run(tracking_uri: &str, meta: &MetaData) -> anyhow::Result<MlflowRun> {
+    let mut tags = vec![json!({"key": "kioku.timestamp", "value": meta.timestamp})];
+    if let Some(revision) = &meta.revision {
+        tags.push(json!({"key": "mlflow.source.git.commit", "value": revision}));
+    }
+    if !meta.tags.is_empty() {
+        tags.push(json!({"key": "kioku.tags", "value": meta.tags.join(",")}));
+    }
+    for (key, value) in &meta.extra {
+        let value = match value {
+            serde_json::Value::String(s) => s.clone(),
+            value => value.to_string(),
+        };
+        tags.push(json!({"key": format!("kioku.{}", key), "value": value}));
+    }
+    let experiment_id = env::var("MLFLOW_EXPERIMENT_ID").unwrap_or_else(|_| "0".to_string());
+    let response = post(
+        tracking_uri,
+        "runs/create",
+        &json!({
+            "experiment_id": experiment_id,
+            "run_name": meta.label,
+            "start_time": millis(&meta.timestamp),
+            "tags": tags,
+        }),
+    )?;
+    let run_id = response["run"]["info"]["run_id"]
+        .as_str()
+        .context("MLflow did not return the id of the created run")?;
+    Ok(MlflowRun {
+        tracking_uri: tracking_uri.to_string(),
+        run_id: run_id.to_string(),
+    })
+}
+
+/// Mark the MLflow run of a finished record as terminated
+pub fn finish_run(run: &MlflowRun, meta: &MetaData) -> anyhow::Result<()> {
+    let status = match meta.status {
+        Some(Status::Running) => "RUNNING",
+        Some(Status::Failed) => "FAILED",
+        Some(Status::Reserved) => "SCHEDULED",
+        Some(Status::Success) | None => "FINISHED",
+    };
+    post(
+        &run.tracking_uri,
+        "runs/update",
+        &json!({
+            "run_id": run.run_id,
+            "status": status,
+            "end_time": meta.finished.as_deref().and_then(millis),
+        }),
+    )?;
+    Ok(())
+}
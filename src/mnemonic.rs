@@ -0,0 +1,136 @@
+//! Reversible mapping between identifiers and names.
+//!
+//! Identifiers are read as strings over the alphabet of hexadecimal digits and '-', which covers
+//! decimal numbers, hashes and UUIDs alike. Both sides use bijective numeration (digits run from
+//! 1 to the base), so leading zeros survive the round trip. Case and a `0x` prefix do not: hex
+//! identifiers decode to bare lowercase digits.
+use anyhow::bail;
+
+static ALPHABET: &[u8] = b"0123456789abcdef-";
+
+/// Arbitrary precision natural number, little endian base 2^32 limbs
+#[derive(Default)]
+struct Natural(Vec<u32>);
+
+impl Natural {
+    fn is_zero(&self) -> bool {
+        self.0.iter().all(|&limb| limb == 0)
+    }
+
+    fn mul_add(&mut self, factor: u32, addend: u32) {
+        let mut carry = addend as u64;
+        for limb in self.0.iter_mut() {
+            let value = *limb as u64 * factor as u64 + carry;
+            *limb = value as u32;
+            carry = value >> 32;
+        }
+        if carry > 0 {
+            self.0.push(carry as u32);
+        }
+    }
+
+    /// Divide in place, returning the remainder
+    fn div_rem(&mut self, divisor: u32) -> u32 {
+        let mut rem = 0u64;
+        for limb in self.0.iter_mut().rev() {
+            let value = (rem << 32) | *limb as u64;
+            *limb = (value / divisor as u64) as u32;
+            rem = value % divisor as u64;
+        }
+        rem as u32
+    }
+
+    fn decrement(&mut self) {
+        for limb in self.0.iter_mut() {
+            let (value, borrow) = limb.overflowing_sub(1);
+            *limb = value;
+            if !borrow {
+                break;
+            }
+        }
+    }
+
+    fn from_digits(digits: impl Iterator<Item = u32>, base: u32) -> Natural {
+        let mut n = Natural::default();
+        for digit in digits {
+            n.mul_add(base, digit + 1);
+        }
+        n
+    }
+
+    fn into_digits(mut self, base: u32) -> Vec<u32> {
+        let mut digits = Vec::new();
+        while !self.is_zero() {
+            self.decrement();
+            digits.push(self.div_rem(base));
+        }
+        digits.reverse();
+        digits
+    }
+}
+
+/// Map `id` onto words, ignoring its case and a leading `0x`
+pub fn encode<'a>(id: &str, words: &[&'a str]) -> anyhow::Result<Vec<&'a str>> {
+    let id = id.trim().to_ascii_lowercase();
+    let id = id.strip_prefix("0x").unwrap_or(&id);
+    if id.is_empty() {
+        bail!("Nothing to encode");
+    }
+    let digits = id
+        .bytes()
+        .map(|c| match ALPHABET.iter().position(|&a| a == c) {
+            Some(i) => Ok(i as u32),
+            None => bail!(
+                "Cannot encode {}, only decimal or hexadecimal digits and '-' are supported",
+                id
+            ),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let base = u32::try_from(words.len())?;
+    if base < 2 {
+        bail!("The wordlist needs at least two distinct words to encode identifiers");
+    }
+    Ok(
+        Natural::from_digits(digits.into_iter(), ALPHABET.len() as u32)
+            .into_digits(base)
+            .into_iter()
+            .map(|i| words[i as usize])
+            .collect(),
+    )
+}
+
+pub fn decode(name: &[&str], words: &[&str]) -> anyhow::Result<String> {
+    let digits = name
+        .iter()
+        .map(|word| {
+            let word = word.to_lowercase();
+            match words.iter().position(|w| w.to_lowercase() == word) {
+                Some(i) => Ok(i as u32),
+                None => bail!("{} is not in the wordlist", word),
+            }
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let base = u32::try_from(words.len())?;
+    Ok(Natural::from_digits(digits.into_iter(), base)
+        .into_digits(ALPHABET.len() as u32)
+        .into_iter()
+        .map(|i| ALPHABET[i as usize] as char)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_round_trip() {
+        let words = ["bold", "otter", "paris", "gene", "ruin"];
+        for id in ["0", "007", "12345", "6b862e08", "123e4567-e89b-12d3"] {
+            let name = encode(id, &words).unwrap();
+            assert_eq!(decode(&name, &words).unwrap(), id);
+        }
+        let name = encode("0xFF", &words).unwrap();
+        assert_eq!(name, encode("ff", &words).unwrap());
+        assert_eq!(decode(&name, &words).unwrap(), "ff");
+    }
+}
@@ -0,0 +1,622 @@
+use crate::wordlist::Word;
+use anyhow::Context;
+use clap::ValueEnum;
+use rand::distr::weighted::WeightedIndex;
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Casing applied to the words of a generated name
+#[derive(Clone, Copy, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Case {
+    /// bold-otter-paris
+    Kebab,
+    /// bold_otter_paris
+    Snake,
+    /// boldOtterParis
+    Camel,
+    /// BoldOtterParis
+    Pascal,
+    /// Bold-Otter-Paris
+    Title,
+}
+
+impl Case {
+    fn default_separator(self) -> &'static str {
+        match self {
+            Case::Kebab | Case::Title => "-",
+            Case::Snake => "_",
+            Case::Camel | Case::Pascal => "",
+        }
+    }
+
+    fn apply(self, word: &str, index: usize) -> String {
+        let capitalize = match self {
+            Case::Kebab | Case::Snake => false,
+            Case::Camel => index > 0,
+            Case::Pascal | Case::Title => true,
+        };
+        // Scripts without case, such as Japanese, pass through unchanged
+        let lower = word.to_lowercase();
+        if capitalize {
+            let mut chars = lower.chars();
+            chars
+                .next()
+                .map(|first| {
+                    // Title case keeps only the first letter of expansions such as ß -> SS
+                    let upper: String = first.to_uppercase().collect();
+                    let mut upper = upper.chars();
+                    upper.next().into_iter().collect::<String>()
+                        + &upper.as_str().to_lowercase()
+                        + chars.as_str()
+                })
+                .unwrap_or_default()
+        } else {
+            lower
+        }
+    }
+}
+
+/// Suffix appended to the words of a name to make it unique
+#[derive(Clone, Copy, Default, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Suffix {
+    /// No suffix
+    #[default]
+    None,
+    /// Short random base36 hash, e.g. 3k9x2a
+    Hash,
+    /// Counter increasing with every generated name on this machine
+    Counter,
+    /// Compact timestamp, e.g. 20260115074009
+    Timestamp,
+}
+
+fn base36(mut n: u64) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut out = Vec::new();
+    loop {
+        out.push(DIGITS[(n % 36) as usize]);
+        n /= 36;
+        if n == 0 {
+            break;
+        }
+    }
+    out.reverse();
+    String::from_utf8(out).unwrap_or_default()
+}
+
+/// The persistent counter behind counter suffixes. Callers hold the lock on `Counter::path`
+/// from reading the counter until they are done with it, so concurrent kioku processes never
+/// hand out the same value.
+pub struct Counter {
+    path: PathBuf,
+    /// Last value handed out
+    value: u64,
+    /// Preview names without advancing the counter
+    dry_run: bool,
+}
+
+impl Counter {
+    /// The counter file, created along with its directory when the counter first advances
+    pub fn path() -> anyhow::Result<PathBuf> {
+        Ok(crate::config::data_dir()?.join("counter"))
+    }
+
+    pub fn read(path: &Path, dry_run: bool) -> anyhow::Result<Counter> {
+        let value = match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid counter file {}", path.to_string_lossy()))?,
+            Err(_) => 0,
+        };
+        Ok(Counter {
+            path: path.to_path_buf(),
+            value,
+            dry_run,
+        })
+    }
+
+    /// The value of the next name, which stays the same until `advance` is called
+    pub fn next(&self) -> u64 {
+        self.value + 1
+    }
+
+    /// Hand out the next value, once a name using it is accepted
+    pub fn advance(&mut self) -> anyhow::Result<()> {
+        self.value += 1;
+        if self.dry_run {
+            return Ok(());
+        }
+        crate::metadata::write_atomic(&self.path, |writer| {
+            writeln!(writer, "{}", self.value)?;
+            Ok(())
+        })
+    }
+}
+
+impl Suffix {
+    pub fn generate(self, counter: Option<&Counter>) -> anyhow::Result<Option<String>> {
+        Ok(match self {
+            Suffix::None => None,
+            Suffix::Hash => {
+                let mut hasher = Sha256::new();
+                hasher.update(chrono::Local::now().to_rfc3339());
+                hasher.update(std::process::id().to_le_bytes());
+                hasher.update(rand::rng().random::<u64>().to_le_bytes());
+                let digest = hasher.finalize();
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&digest[..8]);
+                // Reduce to at most six base36 digits
+                let n = u64::from_le_bytes(bytes) % 36u64.pow(6);
+                Some(format!("{:0>6}", base36(n)))
+            }
+            Suffix::Counter => Some(
+                counter
+                    .context("The counter suffix needs the counter")?
+                    .next()
+                    .to_string(),
+            ),
+            Suffix::Timestamp => Some(chrono::Local::now().format("%Y%m%d%H%M%S").to_string()),
+        })
+    }
+}
+
+/// Target system whose naming rules generated names must satisfy
+#[derive(Clone, Copy, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Charset {
+    /// DNS label: lowercase letters, digits and '-', at most 63 characters
+    Dns,
+    /// Kubernetes label value: letters, digits, '-', '_' and '.', at most 63 characters
+    K8s,
+    /// S3 bucket name: lowercase letters, digits, '-' and '.', 3 to 63 characters
+    S3,
+    /// File name: no path separators or characters reserved on Windows, at most 255 bytes
+    Filename,
+}
+
+impl Charset {
+    /// Limit in bytes, which equals characters for the ascii only charsets
+    fn max_len(self) -> usize {
+        match self {
+            Charset::Dns | Charset::K8s | Charset::S3 => 63,
+            Charset::Filename => 255,
+        }
+    }
+
+    fn allows(self, name: &str) -> bool {
+        let alnum_ends = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && name.ends_with(|c: char| c.is_ascii_alphanumeric());
+        match self {
+            Charset::Dns => {
+                alnum_ends
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            }
+            Charset::K8s => {
+                alnum_ends
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            }
+            Charset::S3 => {
+                alnum_ends
+                    && name.len() >= 3
+                    && !name.contains("..")
+                    && name.chars().all(|c| {
+                        c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '.')
+                    })
+            }
+            Charset::Filename => crate::platform::valid_file_name(name),
+        }
+    }
+}
+
+/// Requirements a generated name has to meet, names that do not are resampled
+#[derive(Default)]
+pub struct Constraints {
+    pub max_len: Option<usize>,
+    pub charset: Option<Charset>,
+}
+
+impl Constraints {
+    /// Refuse a scope no label could satisfy: a namespace or prefix is separated by '/', which
+    /// only file names allow, as a directory level
+    pub fn check_scope(&self, scope: &Scope) -> anyhow::Result<()> {
+        if let Some(charset @ (Charset::Dns | Charset::K8s | Charset::S3)) = self.charset
+            && (scope.namespace.is_some() || scope.prefix.is_some())
+        {
+            anyhow::bail!(
+                "--charset {} does not allow '/', it cannot be combined with --namespace or --prefix",
+                charset
+                    .to_possible_value()
+                    .map_or_else(String::new, |value| value.get_name().to_string())
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether `name`, placed in its scope as `label`, meets the constraints. The length covers
+    /// the whole label, the charset only the name, as the levels of a scope are checked when
+    /// they are given. `max_len` counts user-perceived characters (graphemes), so that a letter
+    /// with combining accents or a Japanese kana counts as one.
+    pub fn allows(&self, name: &str, label: &str) -> bool {
+        self.max_len
+            .is_none_or(|max_len| label.graphemes(true).count() <= max_len)
+            && self
+                .charset
+                .is_none_or(|charset| name.len() <= charset.max_len() && charset.allows(name))
+    }
+}
+
+/// Directory level placed before generated names, e.g. the 2024-06-12 in
+/// 2024-06-12/bold-otter-paris
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum Prefix {
+    #[default]
+    None,
+    /// The local date, e.g. 2024-06-12
+    Date,
+    /// The ISO week of the local date, e.g. 2024-W24
+    IsoWeek,
+    /// Any other text
+    Literal(String),
+}
+
+impl std::str::FromStr for Prefix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Prefix, String> {
+        Ok(match s {
+            "none" => Prefix::None,
+            "date" => Prefix::Date,
+            "iso-week" => Prefix::IsoWeek,
+            _ => Prefix::Literal(parse_namespace(s)?),
+        })
+    }
+}
+
+impl TryFrom<String> for Prefix {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Prefix, String> {
+        s.parse()
+    }
+}
+
+impl Prefix {
+    /// The prefix of names generated now
+    pub fn render(&self) -> Option<String> {
+        match self {
+            Prefix::None => None,
+            Prefix::Date => Some(chrono::Local::now().format("%Y-%m-%d").to_string()),
+            Prefix::IsoWeek => Some(chrono::Local::now().format("%G-W%V").to_string()),
+            Prefix::Literal(prefix) => Some(prefix.clone()),
+        }
+    }
+}
+
+/// Check a namespace or literal prefix. Its levels, separated by '/', become directory levels
+/// of run directories, so each has to be a valid file name.
+pub fn parse_namespace(namespace: &str) -> Result<String, String> {
+    if namespace.split('/').all(crate::platform::valid_file_name) {
+        Ok(namespace.to_string())
+    } else {
+        Err(format!(
+            "{} cannot name a directory on every platform",
+            namespace
+        ))
+    }
+}
+
+/// Namespace and prefix placed before generated names, separated by '/'
+#[derive(Default)]
+pub struct Scope {
+    pub namespace: Option<String>,
+    pub prefix: Option<String>,
+}
+
+impl Scope {
+    pub fn label(&self, name: &str) -> String {
+        let mut label = String::new();
+        for level in [&self.namespace, &self.prefix].into_iter().flatten() {
+            label.push_str(level);
+            label.push('/');
+        }
+        label + name
+    }
+}
+
+/// How the sampled words are joined into a name
+#[derive(Default)]
+pub struct NameStyle {
+    pub case: Option<Case>,
+    pub separator: Option<String>,
+    pub suffix: Suffix,
+}
+
+impl NameStyle {
+    fn separator(&self) -> &str {
+        self.separator
+            .as_deref()
+            .unwrap_or_else(|| self.case.map_or("-", Case::default_separator))
+    }
+
+    pub fn join(&self, words: &[&str]) -> String {
+        let separator = self.separator();
+        match self.case {
+            Some(case) => words
+                .iter()
+                .enumerate()
+                .map(|(i, word)| case.apply(word, i))
+                .collect::<Vec<_>>()
+                .join(separator),
+            None => words.join(separator),
+        }
+    }
+}
+
+/// Draws words from a wordlist proportionally to their weights
+pub struct Sampler<'a> {
+    words: &'a [Word],
+    index: WeightedIndex<f64>,
+}
+
+impl<'a> Sampler<'a> {
+    pub fn new(words: &'a [Word]) -> anyhow::Result<Sampler<'a>> {
+        let index = WeightedIndex::new(words.iter().map(|word| word.weight))
+            .map_err(crate::error::WordlistErr::NoUsableWords)?;
+        Ok(Sampler { words, index })
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> &'a str {
+        self.words[self.index.sample(rng)].text.as_str()
+    }
+}
+
+/// Where the words of a name come from
+#[derive(Clone, Copy, Default, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Generator {
+    /// Sample words from the wordlist
+    #[default]
+    Words,
+    /// Build pronounceable pseudo-words from syllables
+    Phonetic,
+}
+
+pub enum Source<'a> {
+    Words(Sampler<'a>),
+    /// Pseudo-words with this many syllables
+    Phonetic(usize),
+}
+
+const ONSETS: &[u8] = b"bdfghjklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+const CODAS: &[u8] = b"klmnrst";
+
+/// A pronounceable pseudo-word made of consonant-vowel and consonant-vowel-consonant syllables
+fn phonetic_word(rng: &mut impl Rng, syllables: usize) -> String {
+    let mut word = String::new();
+    for _ in 0..syllables.max(1) {
+        word.push(*ONSETS.choose(rng).unwrap_or(&b'k') as char);
+        word.push(*VOWELS.choose(rng).unwrap_or(&b'a') as char);
+        if rng.random_bool(0.3) {
+            word.push(*CODAS.choose(rng).unwrap_or(&b'n') as char);
+        }
+    }
+    word
+}
+
+/// Candidate names drawn from each random stream per round of parallel generation
+const STREAM_LEN: usize = 256;
+
+/// Independent random streams derived from one seed, a digest as seed derives names
+/// deterministically. Candidates are sampled from all streams in parallel and concatenated in
+/// stream order, so a seed gives the same names regardless of the number of threads, and the
+/// first stream alone produces the same names whether one or a few hundred are generated.
+pub struct Streams(Vec<ChaCha12Rng>);
+
+impl Streams {
+    /// Enough streams to share the sampling of `count` names
+    pub fn new(seed: [u8; 32], count: usize) -> Streams {
+        let streams = count.div_ceil(STREAM_LEN).max(1) as u64;
+        Streams(
+            (0..streams)
+                .map(|stream| {
+                    let mut rng = ChaCha12Rng::from_seed(seed);
+                    rng.set_stream(stream);
+                    rng
+                })
+                .collect(),
+        )
+    }
+
+    /// Sample the words of at least `n` candidate names, to be completed with `finish_name`
+    pub fn sample(
+        &mut self,
+        source: &Source,
+        num_words: usize,
+        style: &NameStyle,
+        n: usize,
+    ) -> Vec<String> {
+        let per_stream = n.div_ceil(self.0.len());
+        self.0
+            .par_iter_mut()
+            .flat_map_iter(|rng| {
+                (0..per_stream)
+                    .map(|_| sample_words(source, num_words, style, rng))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+/// SHA-256 digest of a file, or of stdin when `path` is `-`
+pub fn content_digest(path: &str) -> anyhow::Result<[u8; 32]> {
+    let contents = if path == "-" {
+        let mut contents = Vec::new();
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut contents)
+            .context("Failed to read stdin")?;
+        contents
+    } else {
+        fs::read(path).with_context(|| format!("Failed to read file {}", path))?
+    };
+    Ok(Sha256::digest(&contents).into())
+}
+
+/// The words of a name joined in `style`
+fn sample_words(
+    source: &Source,
+    num_words: usize,
+    style: &NameStyle,
+    rng: &mut impl Rng,
+) -> String {
+    match source {
+        Source::Words(sampler) => {
+            let words: Vec<&str> = (0..num_words).map(|_| sampler.sample(rng)).collect();
+            style.join(&words)
+        }
+        Source::Phonetic(syllables) => {
+            let words: Vec<String> = (0..num_words)
+                .map(|_| phonetic_word(rng, *syllables))
+                .collect();
+            style.join(&words.iter().map(String::as_str).collect::<Vec<_>>())
+        }
+    }
+}
+
+/// Complete sampled words with the suffix of `style`. Suffixes are not sampled in parallel, as
+/// the counter suffix depends on the names accepted before.
+pub fn finish_name(
+    mut name: String,
+    style: &NameStyle,
+    counter: Option<&Counter>,
+) -> anyhow::Result<String> {
+    if let Some(suffix) = style.suffix.generate(counter)? {
+        if !name.is_empty() {
+            name.push_str(style.separator());
+        }
+        name.push_str(&suffix);
+    }
+    // Separators and case mappings may leave decomposed characters behind
+    Ok(name.nfc().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unicode_words() {
+        let parsed: Vec<String> = crate::wordlist::parse_lines(std::io::Cursor::new(
+            "e\u{301}cole\nさくら\u{3000}2\nfoo1\n",
+        ))
+        .into_iter()
+        .map(|word| word.text)
+        .collect();
+        assert_eq!(parsed, ["\u{e9}cole", "さくら"]);
+        assert_eq!(Case::Pascal.apply("ßuppe", 0), "Ssuppe");
+        assert_eq!(Case::Title.apply("さくら", 0), "さくら");
+        let constraints = Constraints {
+            max_len: Some(5),
+            ..Default::default()
+        };
+        assert!(constraints.allows("e\u{301}cole", "e\u{301}cole"));
+        assert!(!constraints.allows("さくら-やま", "さくら-やま"));
+    }
+
+    #[test]
+    fn streams_are_deterministic() {
+        let source = Source::Phonetic(2);
+        let style = NameStyle::default();
+        let sample = |count, n| Streams::new([7; 32], count).sample(&source, 2, &style, n);
+        assert_eq!(sample(1, 3), sample(200, 3));
+        let batch = sample(1000, 1000);
+        assert_eq!(batch.len(), 1000);
+        assert_eq!(batch, sample(1000, 1000));
+        assert_eq!(batch[..3], sample(1, 3));
+    }
+
+    #[test]
+    fn scoped_labels() {
+        assert_eq!("date".parse(), Ok(Prefix::Date));
+        assert_eq!("sweep".parse(), Ok(Prefix::Literal("sweep".to_string())));
+        assert!("con".parse::<Prefix>().is_err());
+        let scope = Scope {
+            namespace: Some("vision".to_string()),
+            prefix: Prefix::IsoWeek.render(),
+        };
+        let label = scope.label("bold-otter-paris");
+        assert!(label.starts_with("vision/20"));
+        assert!(label.ends_with("/bold-otter-paris"));
+        assert_eq!(
+            Scope::default().label("bold-otter-paris"),
+            "bold-otter-paris"
+        );
+    }
+
+    #[test]
+    fn constraints_cover_scoped_labels() {
+        let scope = Scope {
+            namespace: Some("team".to_string()),
+            prefix: None,
+        };
+        let max_len = Constraints {
+            max_len: Some(20),
+            ..Default::default()
+        };
+        assert!(max_len.check_scope(&scope).is_ok());
+        let label = scope.label("owner-blend-fire");
+        assert!(max_len.allows("owner-blend-fire", "owner-blend-fire"));
+        assert!(!max_len.allows("owner-blend-fire", &label));
+        for charset in [Charset::Dns, Charset::K8s, Charset::S3] {
+            let constraints = Constraints {
+                charset: Some(charset),
+                ..Default::default()
+            };
+            assert!(constraints.check_scope(&scope).is_err());
+            assert!(constraints.check_scope(&Scope::default()).is_ok());
+        }
+        let filename = Constraints {
+            charset: Some(Charset::Filename),
+            ..Default::default()
+        };
+        assert!(filename.check_scope(&scope).is_ok());
+        assert!(filename.allows("octet-cone-crud", &scope.label("octet-cone-crud")));
+    }
+
+    #[test]
+    fn counters_advance_for_accepted_names() {
+        let path = std::env::temp_dir().join(format!("kioku-counter-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let style = NameStyle {
+            suffix: Suffix::Counter,
+            ..Default::default()
+        };
+        let mut counter = Counter::read(&path, false).unwrap();
+        let first = finish_name("a".to_string(), &style, Some(&counter)).unwrap();
+        let rejected = finish_name("b".to_string(), &style, Some(&counter)).unwrap();
+        assert_eq!((first.as_str(), rejected.as_str()), ("a-1", "b-1"));
+        counter.advance().unwrap();
+        assert_eq!(Counter::read(&path, false).unwrap().next(), 2);
+        let mut preview = Counter::read(&path, true).unwrap();
+        preview.advance().unwrap();
+        assert_eq!(preview.next(), 3);
+        assert_eq!(Counter::read(&path, false).unwrap().next(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+use crate::metadata::MetaData;
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+
+/// Attempts made to deliver a notification before giving up
+const ATTEMPTS: u32 = 4;
+/// Delay before the first retry, doubled for every further one
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Only server errors, rate limiting and connection problems are worth retrying
+fn is_transient(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::StatusCode(code) => *code >= 500 || *code == 429,
+        ureq::Error::Io(_)
+        | ureq::Error::Timeout(_)
+        | ureq::Error::HostNotFound
+        | ureq::Error::ConnectionFailed => true,
+        _ => false,
+    }
+}
+
+fn send(url: &str, headers: &BTreeMap<String, String>, body: &str) -> Result<(), ureq::Error> {
+    let mut request = ureq::post(url).header("Content-Type", "application/json");
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request.send(body).map(|_| ())
+}
+
+/// POST the metadata of each record as JSON to `url`, retrying transient failures with
+/// exponential backoff
+pub fn notify(
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    records: &[MetaData],
+) -> anyhow::Result<()> {
+    for meta in records {
+        let body = serde_json::to_string(meta)?;
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match send(url, headers, &body) {
+                Ok(()) => break,
+                Err(e) if attempt < ATTEMPTS && is_transient(&e) => {
+                    log::debug!(
+                        "Notifying {} failed ({}), retrying in {:?}",
+                        url,
+                        e,
+                        backoff
+                    );
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to notify {} about {}", url, meta.label));
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use anyhow::Context;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Names Windows reserves for devices in every directory, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Whether `name` can name a file on every supported platform. Checked everywhere, so run
+/// directories created on Linux can still be checked out on Windows.
+pub fn valid_file_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default();
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.ends_with(['.', ' '])
+        && !RESERVED_NAMES
+            .iter()
+            .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+        && !name.chars().any(|c| {
+            c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+        })
+}
+
+/// A path as recorded in metadata, with `/` as separator on every platform so records stay
+/// usable when shared between machines
+pub fn portable(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        path.replace('\\', "/")
+    } else {
+        path.into_owned()
+    }
+}
+
+/// Absolute form of `path` with symlinks resolved. On Windows this avoids the `\\?\` prefix
+/// where the plain path works, which other tools and other path functions do not expect.
+pub fn canonicalize(path: &Path) -> std::io::Result<PathBuf> {
+    let absolute = path.canonicalize()?;
+    if cfg!(windows) {
+        let text = absolute.to_string_lossy();
+        if let Some(plain) = text.strip_prefix(r"\\?\")
+            && plain.as_bytes().get(1) == Some(&b':')
+            && plain.len() < 260
+        {
+            return Ok(PathBuf::from(plain));
+        }
+    }
+    Ok(absolute)
+}
+
+/// Temporary name next to `path`, for replacing it in one step
+fn temporary(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Point the directory link `link` at the directory `target`, replacing an earlier link.
+/// Links are relative symlinks on Unix. On Windows, creating symlinks needs Developer Mode or
+/// administrator rights, so a junction is created instead where that fails. Returns false if
+/// no link could be created.
+pub fn link_dir(target: &Path, link: &Path) -> anyhow::Result<bool> {
+    let tmp = temporary(link);
+    if !create_link(target, &tmp)? {
+        return Ok(false);
+    }
+    let result = replace_link(&tmp, link)
+        .with_context(|| format!("Failed to update link {}", link.to_string_lossy()));
+    if result.is_err() {
+        let _ = remove_link(&tmp);
+    }
+    result.map(|()| true)
+}
+
+#[cfg(unix)]
+fn create_link(target: &Path, link: &Path) -> anyhow::Result<bool> {
+    let base = link.parent().unwrap_or(Path::new(""));
+    let relative = target.strip_prefix(base).unwrap_or(target);
+    std::os::unix::fs::symlink(relative, link)
+        .with_context(|| format!("Failed to create symlink {}", link.to_string_lossy()))?;
+    Ok(true)
+}
+
+#[cfg(windows)]
+fn create_link(target: &Path, link: &Path) -> anyhow::Result<bool> {
+    let base = link.parent().unwrap_or(Path::new(""));
+    let relative = target.strip_prefix(base).unwrap_or(target);
+    if std::os::windows::fs::symlink_dir(relative, link).is_ok() {
+        return Ok(true);
+    }
+    // Junctions need no privileges but an absolute target
+    let absolute = std::path::absolute(target)
+        .with_context(|| format!("Invalid directory {}", target.to_string_lossy()))?;
+    match junction::create(&absolute, link) {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            log::debug!(
+                "Failed to create junction {}: {}",
+                link.to_string_lossy(),
+                e
+            );
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_link(_target: &Path, _link: &Path) -> anyhow::Result<bool> {
+    Ok(false)
+}
+
+/// Remove a link without touching the directory it points to
+fn remove_link(link: &Path) -> std::io::Result<()> {
+    if cfg!(windows) {
+        // Directory symlinks and junctions are removed as directories on Windows
+        fs::remove_dir(link).or_else(|_| fs::remove_file(link))
+    } else {
+        fs::remove_file(link)
+    }
+}
+
+/// Move the link `from` to `to`. Renaming over the old link replaces it atomically on Unix,
+/// Windows cannot rename over a directory so the old link is removed first.
+fn replace_link(from: &Path, to: &Path) -> std::io::Result<()> {
+    if cfg!(windows) && to.symlink_metadata().is_ok() {
+        remove_link(to)?;
+    }
+    fs::rename(from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_valid_everywhere() {
+        assert!(valid_file_name("gene-ruin-note"));
+        assert!(valid_file_name("console"));
+        assert!(!valid_file_name("con"));
+        assert!(!valid_file_name("Aux.txt"));
+        assert!(!valid_file_name("note."));
+        assert!(!valid_file_name("a:b"));
+    }
+
+    #[test]
+    fn links_are_replaced() {
+        let root = std::env::temp_dir().join(format!("kioku-link-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for run in ["first", "second"] {
+            fs::create_dir_all(root.join(run)).unwrap();
+            fs::write(root.join(run).join("meta.json"), run).unwrap();
+        }
+        let link = root.join("latest");
+        assert!(link_dir(&root.join("first"), &link).unwrap());
+        assert!(link_dir(&root.join("second"), &link).unwrap());
+        let contents = fs::read_to_string(link.join("meta.json")).unwrap();
+        remove_link(&link).unwrap();
+        assert!(root.join("first").join("meta.json").exists());
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(contents, "second");
+    }
+}
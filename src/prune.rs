@@ -0,0 +1,135 @@
+use crate::metadata::{Clock, MetaData, Status};
+use anyhow::{Context, bail};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Parse an age such as `36h`, `90d`, `8w` or `2y`
+pub fn parse_age(age: &str) -> anyhow::Result<chrono::Duration> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid age {}, expected a number followed by m, h, d, w or y",
+            age
+        )
+    };
+    let (i, unit) = age.char_indices().last().ok_or_else(invalid)?;
+    let count: i64 = age[..i]
+        .parse()
+        .with_context(|| format!("Invalid age {}, expected for example 90d", age))?;
+    if count < 0 {
+        bail!("Invalid age {}, ages cannot be negative", age);
+    }
+    match unit {
+        'm' => chrono::Duration::try_minutes(count),
+        'h' => chrono::Duration::try_hours(count),
+        'd' => chrono::Duration::try_days(count),
+        'w' => chrono::Duration::try_weeks(count),
+        'y' => count.checked_mul(365).and_then(chrono::Duration::try_days),
+        _ => return Err(invalid()),
+    }
+    .with_context(|| format!("Age {} is too long", age))
+}
+
+/// Whether a record started before `cutoff` and has `status` if given.
+/// Records whose timestamp `clock` cannot read are never pruned.
+pub fn is_pruned(
+    record: &Value,
+    cutoff: chrono::DateTime<chrono::Local>,
+    status: Option<Status>,
+    clock: &Clock,
+) -> bool {
+    let Ok(meta) = serde_json::from_value::<MetaData>(record.clone()) else {
+        return false;
+    };
+    let old = clock
+        .parse(&meta.timestamp)
+        .is_some_and(|started| started < cutoff);
+    old && status.is_none_or(|status| meta.status == Some(status))
+}
+
+/// Number of records whose timestamp `clock` cannot read, which are never pruned
+pub fn unreadable(records: &[Value], clock: &Clock) -> usize {
+    records
+        .iter()
+        .filter(|record| {
+            record
+                .get("timestamp")
+                .and_then(Value::as_str)
+                .is_none_or(|timestamp| clock.parse(timestamp).is_none())
+        })
+        .count()
+}
+
+fn write_lines(writer: &mut impl Write, records: &[Value]) -> anyhow::Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut *writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Append records to an archive as JSON lines, gzip compressed if the name ends in `.gz`.
+/// Each call adds a gzip member, which `zcat` and other readers concatenate.
+pub fn archive(path: &Path, records: &[Value]) -> anyhow::Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open archive {}", path.to_string_lossy()))?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        write_lines(&mut encoder, records)?;
+        encoder.finish()?.sync_all()?;
+    } else {
+        write_lines(&mut file, records)?;
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages_and_selection() {
+        assert_eq!(parse_age("90d").unwrap(), chrono::Duration::days(90));
+        assert_eq!(parse_age("2w").unwrap(), chrono::Duration::days(14));
+        assert!(parse_age("90").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("").is_err());
+        assert!(parse_age("9é").is_err());
+        assert!(parse_age("-3d").is_err());
+        assert!(parse_age("99999999999y").is_err());
+
+        let cutoff = chrono::Local::now() - parse_age("30d").unwrap();
+        let record = |timestamp: &str, status: &str| {
+            serde_json::json!({
+                "schema_version": 1,
+                "label": "a",
+                "revision": null,
+                "timestamp": timestamp,
+                "status": status,
+            })
+        };
+        let old = record("2020-01-01T00:00:00+00:00", "failed");
+        let clock = Clock::default();
+        assert!(is_pruned(&old, cutoff, None, &clock));
+        assert!(is_pruned(&old, cutoff, Some(Status::Failed), &clock));
+        assert!(!is_pruned(&old, cutoff, Some(Status::Success), &clock));
+        let recent = record(&chrono::Local::now().to_rfc3339(), "failed");
+        assert!(!is_pruned(&recent, cutoff, None, &clock));
+        let yesterday = record("yesterday", "failed");
+        assert!(!is_pruned(&yesterday, cutoff, None, &clock));
+
+        let custom = record("01.01.2020 00:00", "failed");
+        assert!(!is_pruned(&custom, cutoff, None, &clock));
+        assert_eq!(unreadable(&[custom.clone(), yesterday, old], &clock), 2);
+        let clock = Clock::new(None, Some("%d.%m.%Y %H:%M".to_string())).unwrap();
+        assert!(is_pruned(&custom, cutoff, None, &clock));
+        assert_eq!(unreadable(&[custom], &clock), 0);
+    }
+}
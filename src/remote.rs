@@ -0,0 +1,124 @@
+use crate::config::Config;
+use crate::error::TypedContext;
+use crate::metadata::MetaData;
+use anyhow::bail;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The records as JSON lines, as appended to remote logs
+pub fn jsonl(records: &[MetaData]) -> anyhow::Result<String> {
+    let mut body = String::new();
+    for meta in records {
+        body.push_str(&serde_json::to_string(meta)?);
+        body.push('\n');
+    }
+    Ok(body)
+}
+
+/// Quote `s` for a POSIX shell
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Split `ssh://[user@]host[:port]/path` into the destination, the port and the path
+fn parse_ssh(url: &str) -> anyhow::Result<(&str, Option<&str>, &str)> {
+    let rest = &url["ssh://".len()..];
+    let Some((authority, path)) = rest.split_once('/') else {
+        bail!(
+            "Invalid remote {}, expected ssh://[user@]host[:port]/path",
+            url
+        );
+    };
+    if path.is_empty() {
+        bail!("Invalid remote {}, no path given", url);
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+    if host.is_empty() || host.starts_with('-') {
+        bail!(
+            "Invalid remote {}, the host cannot be empty or start with '-'",
+            url
+        );
+    }
+    if let Some(port) = port
+        && port.parse::<u16>().is_err()
+    {
+        bail!("Invalid remote {}, the port {} is not a number", url, port);
+    }
+    Ok((host, port, path))
+}
+
+/// Append over ssh, `ssh://[user@]host[:port]/path` with the path relative to the home
+/// directory unless it starts with a further `/`
+pub fn append_ssh(url: &str, body: &str) -> anyhow::Result<()> {
+    let (host, port, path) = parse_ssh(url)?;
+    let mut ssh = Command::new("ssh");
+    if let Some(port) = port {
+        ssh.args(["-p", port]);
+    }
+    let path = shell_quote(path);
+    let mut child = ssh
+        .arg("--")
+        .arg(host)
+        .arg(format!(
+            "mkdir -p \"$(dirname {path})\" && cat >> {path}",
+            path = path
+        ))
+        .stdin(Stdio::piped())
+        .spawn()
+        .typed_context("Failed to run ssh")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(body.as_bytes())
+            .typed_context("Failed to send metadata to ssh")?;
+    }
+    let status = child.wait().typed_context("Failed to wait for ssh")?;
+    if !status.success() {
+        bail!("ssh exited with {}", status);
+    }
+    Ok(())
+}
+
+/// Append over HTTP by POSTing the records as JSON lines
+pub fn append_http(url: &str, body: String, config: &Config) -> anyhow::Result<()> {
+    let mut request = ureq::post(url).header("Content-Type", "application/x-ndjson");
+    if let Some(user) = &config.remote_user {
+        let credentials = format!(
+            "{}:{}",
+            user,
+            config.remote_password.as_deref().unwrap_or_default()
+        );
+        request = request.header(
+            "Authorization",
+            format!("Basic {}", STANDARD.encode(credentials)),
+        );
+    }
+    request
+        .send(body)
+        .with_typed_context(|| format!("Failed to POST metadata to {}", url))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssh_urls_cannot_pass_options() {
+        assert_eq!(
+            parse_ssh("ssh://me@host:2222/runs.jsonl").unwrap(),
+            ("me@host", Some("2222"), "runs.jsonl")
+        );
+        assert_eq!(
+            parse_ssh("ssh://host//var/runs.jsonl").unwrap(),
+            ("host", None, "/var/runs.jsonl")
+        );
+        assert!(parse_ssh("ssh://-oProxyCommand=touch PWNED/x").is_err());
+        assert!(parse_ssh("ssh://host:-oProxyCommand=x/runs.jsonl").is_err());
+        assert!(parse_ssh("ssh://host:22x/runs.jsonl").is_err());
+        assert!(parse_ssh("ssh://host/").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::metadata::{MetaData, Status};
+use std::fmt::Write;
+
+static STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.4em 0.8em; text-align: left; vertical-align: top; }
+th { cursor: pointer; background: #f4f4f4; user-select: none; }
+th:after { content: ' \\2195'; color: #aaa; }
+.running { color: #b58900; } .success { color: #2aa02a; } .failed { color: #d62728; }
+pre { background: #f8f8f8; padding: 0.8em; overflow-x: auto; }
+code { font-size: 0.9em; }";
+
+/// Sorts the table by the clicked column, clicking again reverses the order
+static SCRIPT: &str =
+    "document.querySelectorAll('th').forEach((th, col) => th.addEventListener('click', () => {
+  const body = th.closest('table').tBodies[0];
+  const asc = th.dataset.order !== 'asc';
+  th.dataset.order = asc ? 'asc' : 'desc';
+  const rows = Array.from(body.rows);
+  rows.sort((a, b) => {
+    const x = a.cells[col].dataset.key || a.cells[col].textContent;
+    const y = b.cells[col].dataset.key || b.cells[col].textContent;
+    return (asc ? 1 : -1) * x.localeCompare(y, undefined, {numeric: true});
+  });
+  rows.forEach(row => body.appendChild(row));
+}));";
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Web page of a commit for the origin remote of the current repository, for GitHub, GitLab
+/// and similar hosts. `{revision}` in the returned template is replaced by the commit hash.
+pub fn commit_url_template() -> Option<String> {
+    let repo = git2::Repository::discover(".").ok()?;
+    let remote = repo.find_remote("origin").ok()?;
+    let url = remote.url()?.trim_end_matches(".git");
+    let web = if let Some(rest) = url.strip_prefix("git@") {
+        format!("https://{}", rest.replacen(':', "/", 1))
+    } else if let Some(rest) = url.strip_prefix("ssh://git@") {
+        format!("https://{}", rest)
+    } else if url.starts_with("https://") || url.starts_with("http://") {
+        url.to_string()
+    } else {
+        return None;
+    };
+    Some(format!("{}/commit/{{revision}}", web))
+}
+
+fn status_name(status: Option<Status>) -> &'static str {
+    match status {
+        Some(Status::Running) => "running",
+        Some(Status::Success) => "success",
+        Some(Status::Failed) => "failed",
+        Some(Status::Reserved) => "reserved",
+        None => "",
+    }
+}
+
+/// The abbreviated revision shown in the table, imported revisions need not be ASCII hashes
+fn short(revision: &str) -> String {
+    revision.chars().take(7).collect()
+}
+
+/// Render the runs as a standalone HTML page with a sortable table and the full metadata of
+/// each run
+pub fn render(title: &str, records: &[MetaData], commit_url: Option<&str>) -> String {
+    let mut html = String::new();
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+<style>\n{STYLE}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>{count} runs, generated {now}</p>",
+        title = escape(title),
+        count = records.len(),
+        now = escape(&chrono::Local::now().format("%Y-%m-%d %H:%M").to_string()),
+    );
+    html.push_str(
+        "<table>\n<thead><tr><th>Name</th><th>Started</th><th>Status</th><th>Duration</th>\
+<th>Tags</th><th>Revision</th><th>Details</th></tr></thead>\n<tbody>\n",
+    );
+    for meta in records {
+        let revision = match (&meta.revision, commit_url) {
+            (Some(revision), Some(url)) => format!(
+                "<a href=\"{}\"><code>{}</code></a>",
+                escape(&url.replace("{revision}", revision)),
+                escape(&short(revision))
+            ),
+            (Some(revision), None) => format!("<code>{}</code>", escape(&short(revision))),
+            (None, _) => String::new(),
+        };
+        let duration = meta
+            .duration_secs
+            .map(|secs| format!("{:.1}s", secs))
+            .unwrap_or_default();
+        let details = serde_json::to_string_pretty(meta).unwrap_or_default();
+        let _ = writeln!(
+            html,
+            "<tr><td>{label}</td><td data-key=\"{timestamp}\">{started}</td>\
+<td class=\"{status}\">{status}</td><td data-key=\"{secs}\">{duration}</td><td>{tags}</td>\
+<td>{revision}</td><td><details><summary>metadata</summary><pre>{details}</pre></details></td></tr>",
+            label = escape(&meta.label),
+            timestamp = escape(&meta.timestamp),
+            started = escape(meta.timestamp.get(..16).unwrap_or(&meta.timestamp)).replace('T', " "),
+            status = status_name(meta.status),
+            secs = meta.duration_secs.unwrap_or(0.0),
+            duration = duration,
+            tags = escape(&meta.tags.join(", ")),
+            revision = revision,
+            details = escape(&details),
+        );
+    }
+    let _ = writeln!(
+        html,
+        "</tbody>\n</table>\n<script>\n{SCRIPT}\n</script>\n</body>\n</html>"
+    );
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{Clock, collect};
+
+    #[test]
+    fn render_escapes_and_links_commits() {
+        let mut records = collect(
+            &["<b>bold</b>".to_string(), "gene-ruin-note".to_string()],
+            &[],
+            &Clock::default(),
+        );
+        records[0].revision = Some("0123456789abcdef".to_string());
+        records[0].tags = vec!["a&b".to_string()];
+        records[1].revision = Some("リビジョン番号です".to_string());
+        let html = render(
+            "Runs <all>",
+            &records,
+            Some("https://example.com/repo/commit/{revision}"),
+        );
+        assert!(html.contains("<title>Runs &lt;all&gt;</title>"));
+        assert!(html.contains("<td>&lt;b&gt;bold&lt;/b&gt;</td>"));
+        assert!(!html.contains("<b>bold</b>"));
+        assert!(html.contains("<td>a&amp;b</td>"));
+        assert!(html.contains(
+            "<a href=\"https://example.com/repo/commit/0123456789abcdef\"><code>0123456</code></a>"
+        ));
+        assert!(html.contains("<code>リビジョン番号</code>"));
+        let html = render("Runs", &records[..1], None);
+        assert!(html.contains("<td><code>0123456</code></td>"));
+    }
+}
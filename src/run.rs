@@ -0,0 +1,119 @@
+use anyhow::{Context, bail};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+
+fn create(path: &Path) -> anyhow::Result<fs::File> {
+    fs::File::create(path)
+        .with_context(|| format!("Failed to create file {}", path.to_string_lossy()))
+}
+
+/// Copy everything from `from` both to `to` and to `file`
+fn tee(
+    mut from: impl Read + Send + 'static,
+    mut file: fs::File,
+    mut to: impl Write + Send + 'static,
+) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            let n = from.read(&mut buf)?;
+            if n == 0 {
+                return file.flush();
+            }
+            file.write_all(&buf[..n])?;
+            // Keep capturing to the file even if our own output went away
+            let _ = to.write_all(&buf[..n]).and_then(|_| to.flush());
+        }
+    })
+}
+
+/// Run `command` with `KIOKU_NAME` set to `name` and the variables in `env`, optionally copying
+/// its output streams to files
+pub fn run(
+    command: &[String],
+    name: &str,
+    env: &[(String, String)],
+    stdout: Option<&Path>,
+    stderr: Option<&Path>,
+) -> anyhow::Result<ExitStatus> {
+    let Some((program, args)) = command.split_first() else {
+        bail!("No command given to run");
+    };
+    // Created before the command starts, so a failure leaves no process behind
+    let stdout = stdout.map(create).transpose()?;
+    let stderr = stderr.map(create).transpose()?;
+    let mut child = Command::new(program)
+        .args(args)
+        .env("KIOKU_NAME", name)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdout(if stdout.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
+        .stderr(if stderr.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))?;
+    let mut copies = Vec::new();
+    if let (Some(file), Some(out)) = (stdout, child.stdout.take()) {
+        copies.push(tee(out, file, io::stdout()));
+    }
+    if let (Some(file), Some(err)) = (stderr, child.stderr.take()) {
+        copies.push(tee(err, file, io::stderr()));
+    }
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for {}", program))?;
+    for copy in copies {
+        copy.join()
+            .map_err(|_| anyhow::anyhow!("Output capture thread panicked"))?
+            .context("Failed to capture output")?;
+    }
+    Ok(status)
+}
+
+/// The system shell set up to run `command`
+pub fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Pipe a candidate name through the shell command `filter_cmd`, which accepts the name by
+/// exiting with 0. The name is also available to the command as `$KIOKU_NAME`.
+pub fn filter_accepts(filter_cmd: &str, name: &str) -> anyhow::Result<bool> {
+    let mut child = shell(filter_cmd)
+        .env("KIOKU_NAME", name)
+        .stdin(Stdio::piped())
+        // Keep stdout free for the generated names
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run filter command {}", filter_cmd))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Filters that decide on $KIOKU_NAME alone may exit without reading their input
+        match writeln!(stdin, "{}", name) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => {}
+        }
+    }
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for filter command {}", filter_cmd))?;
+    log::debug!("Filter command {} {}", status, name);
+    Ok(status.success())
+}
@@ -0,0 +1,94 @@
+use std::env;
+use std::fs;
+
+/// Batch scheduler whose job metadata is recorded with each run
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheduler {
+    /// Detect SLURM or PBS from the environment
+    Auto,
+    Slurm,
+    Pbs,
+    /// Do not record scheduler jobs
+    None,
+}
+
+/// The scheduler job a run was started in
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Job {
+    pub scheduler: String,
+    pub job_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_name: Option<String>,
+    /// Id of the array job and index of this task within it, for job arrays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub array_job_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub array_task_id: Option<String>,
+    /// Partition (SLURM) or queue (PBS) the job ran in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nodes: Option<String>,
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn slurm() -> Option<Job> {
+    Some(Job {
+        scheduler: "slurm".to_string(),
+        job_id: var("SLURM_JOB_ID").or_else(|| var("SLURM_JOBID"))?,
+        job_name: var("SLURM_JOB_NAME"),
+        array_job_id: var("SLURM_ARRAY_JOB_ID"),
+        array_task_id: var("SLURM_ARRAY_TASK_ID"),
+        partition: var("SLURM_JOB_PARTITION"),
+        nodes: var("SLURM_JOB_NODELIST").or_else(|| var("SLURM_NODELIST")),
+    })
+}
+
+/// Hosts listed in the PBS node file, which repeats a host for each of its slots
+fn pbs_nodes() -> Option<String> {
+    let contents = fs::read_to_string(var("PBS_NODEFILE")?).ok()?;
+    let mut nodes: Vec<&str> = Vec::new();
+    for node in contents.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if !nodes.contains(&node) {
+            nodes.push(node);
+        }
+    }
+    Some(nodes.join(","))
+}
+
+fn pbs() -> Option<Job> {
+    let job_id = var("PBS_JOBID")?;
+    let array_task_id = var("PBS_ARRAY_INDEX").or_else(|| var("PBS_ARRAYID"));
+    Some(Job {
+        scheduler: "pbs".to_string(),
+        // Array tasks have ids like 1234[7].server, the array job 1234[].server
+        array_job_id: array_task_id.as_ref().and_then(|_| {
+            let (base, rest) = job_id.split_once('[')?;
+            let (_, server) = rest.split_once(']')?;
+            Some(format!("{}[]{}", base, server))
+        }),
+        job_id,
+        job_name: var("PBS_JOBNAME"),
+        array_task_id,
+        partition: var("PBS_QUEUE"),
+        nodes: pbs_nodes(),
+    })
+}
+
+/// The job the current process runs in, if any
+pub fn current_job(scheduler: Scheduler) -> Option<Job> {
+    let job = match scheduler {
+        Scheduler::Auto => slurm().or_else(pbs),
+        Scheduler::Slurm => slurm(),
+        Scheduler::Pbs => pbs(),
+        Scheduler::None => None,
+    };
+    if let Some(job) = &job {
+        log::debug!("Recording {} job {}", job.scheduler, job.job_id);
+    }
+    job
+}
@@ -0,0 +1,464 @@
+use crate::error::KiokuErr;
+use anyhow::{Context, bail};
+use hmac::{Hmac, KeyInit, Mac};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Field of sealed records holding their seal
+pub const FIELD: &str = "seal";
+
+/// Field marking the record of a run removed from a sealed file, which only ever grows
+pub const REMOVED: &str = "removed";
+
+/// Drop the seal fields of a record copied out of a sealed file, which mean nothing elsewhere
+pub fn strip(fields: &mut Map<String, Value>) {
+    fields.remove(FIELD);
+    fields.remove(REMOVED);
+}
+
+/// `prev` of the first record of a sealed file
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Key authenticating the seals, set once from $KIOKU_SEAL_KEY or `seal_key`
+static KEY: OnceLock<Option<String>> = OnceLock::new();
+
+pub fn set_key(key: Option<String>) {
+    let _ = KEY.set(key.filter(|key| !key.is_empty()));
+}
+
+fn key() -> Option<&'static str> {
+    KEY.get().and_then(Option::as_deref)
+}
+
+/// Links a record of a JSON lines file to the records before it
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Seal {
+    /// Position of the record in the file, starting at 0
+    seq: u64,
+    /// `hash` of the record before
+    prev: String,
+    /// SHA-256 of `prev` and the record without its seal
+    hash: String,
+    /// HMAC-SHA256 of `hash` with the seal key, if one is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hmac: Option<String>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn digest(prev: &str, record: &Map<String, Value>) -> anyhow::Result<String> {
+    let unsealed: Map<String, Value> = record
+        .iter()
+        .filter(|(key, _)| *key != FIELD)
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let mut hasher = Sha256::new();
+    hasher.update(prev.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(serde_json::to_vec(&unsealed)?);
+    Ok(hex(&hasher.finalize()))
+}
+
+fn authenticate(key: &str, hash: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(hash.as_bytes());
+    hex(&mac.finalize().into_bytes())
+}
+
+/// Whether the file these records belong to is sealed
+pub fn is_sealed(records: &[Value]) -> bool {
+    records.iter().any(|record| record.get(FIELD).is_some())
+}
+
+/// Whether the JSON lines file `path` is sealed, judging by its first record
+pub fn is_sealed_file(path: &Path) -> anyhow::Result<bool> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!("Failed to read metadata file {}", path.to_string_lossy())
+            });
+        }
+    };
+    for line in io::BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            return Ok(serde_json::from_str::<Value>(&line)
+                .is_ok_and(|record| record.get(FIELD).is_some()));
+        }
+    }
+    Ok(false)
+}
+
+/// The run a record of a sealed file belongs to, records of the same run supersede each other
+fn run_key(record: &Value) -> Option<&str> {
+    record
+        .get("run_id")
+        .or_else(|| record.get("label"))
+        .and_then(Value::as_str)
+}
+
+/// The current records of a sealed file: the last record of each run, in the order the runs were
+/// first recorded, leaving out removed runs
+pub fn current(records: Vec<Value>) -> Vec<Value> {
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut runs: Vec<Option<Value>> = Vec::with_capacity(records.len());
+    for record in records {
+        let key = run_key(&record).map(str::to_string);
+        let state = (record.get(REMOVED) != Some(&Value::Bool(true))).then_some(record);
+        match key {
+            Some(key) => match index.get(&key) {
+                Some(&i) => runs[i] = state,
+                None => {
+                    index.insert(key, runs.len());
+                    runs.push(state);
+                }
+            },
+            None => runs.push(state),
+        }
+    }
+    runs.into_iter().flatten().collect()
+}
+
+/// Seal `records` as following the record sealed with `prev` at position `seq - 1`
+fn seal_from(records: &mut [Value], mut seq: u64, prev: &str) -> anyhow::Result<()> {
+    let mut prev = prev.to_string();
+    for record in records {
+        let Some(fields) = record.as_object_mut() else {
+            bail!("Metadata record is not a JSON object");
+        };
+        let hash = digest(&prev, fields)?;
+        let seal = Seal {
+            seq,
+            prev,
+            hmac: key().map(|key| authenticate(key, &hash)),
+            hash: hash.clone(),
+        };
+        fields.insert(FIELD.to_string(), serde_json::to_value(seal)?);
+        prev = hash;
+        seq += 1;
+    }
+    Ok(())
+}
+
+/// Seal all records of a file that is not sealed yet
+fn seal(records: &mut [Value]) -> anyhow::Result<()> {
+    if key().is_none()
+        && records
+            .iter()
+            .any(|record| record.pointer("/seal/hmac").is_some())
+    {
+        bail!(
+            "The metadata file is sealed with a key, set $KIOKU_SEAL_KEY or seal_key to change it"
+        );
+    }
+    seal_from(records, 0, GENESIS)
+}
+
+/// The last line of `path`, read from the end so appending stays cheap for long files
+fn last_line(path: &Path) -> io::Result<Option<String>> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    let mut window = 4096;
+    loop {
+        let start = len.saturating_sub(window);
+        file.seek(SeekFrom::Start(start))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+        let trimmed = tail.trim_ascii_end();
+        if let Some(newline) = trimmed.iter().rposition(|&b| b == b'\n') {
+            return Ok(Some(
+                String::from_utf8_lossy(&trimmed[newline + 1..]).into_owned(),
+            ));
+        }
+        if start == 0 {
+            return Ok((!trimmed.is_empty()).then(|| String::from_utf8_lossy(trimmed).into_owned()));
+        }
+        window *= 4;
+    }
+}
+
+/// The seal of the last record of `path`, `None` if the file is empty or not sealed. Files
+/// written by older versions may end in a record spanning several lines, which is not sealed.
+pub fn last_seal(path: &Path) -> anyhow::Result<Option<Value>> {
+    let line = last_line(path)
+        .with_context(|| format!("Failed to read metadata file {}", path.to_string_lossy()))?;
+    Ok(line
+        .and_then(|line| serde_json::from_str::<Value>(&line).ok())
+        .and_then(|record| record.get(FIELD).cloned()))
+}
+
+/// A chain is either authenticated with a key throughout or not at all, as `verify` checks every
+/// record the same way
+fn check_key(last: &Seal, key: Option<&str>) -> anyhow::Result<()> {
+    match (&last.hmac, key) {
+        (Some(_), None) => bail!(
+            "The metadata file is sealed with a key, set $KIOKU_SEAL_KEY or seal_key to append to it"
+        ),
+        (None, Some(_)) => bail!(
+            "The metadata file is sealed without a key, unset $KIOKU_SEAL_KEY and seal_key to append to it"
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Append sealed records to the JSON lines file `path`. Records already in the file without a
+/// seal are sealed as well, so the chain covers the whole file.
+pub fn append(path: &Path, records: &[Value]) -> anyhow::Result<()> {
+    let mut records = records.to_vec();
+    match last_seal(path)? {
+        Some(last) => {
+            let last: Seal = serde_json::from_value(last).context("Invalid seal")?;
+            check_key(&last, key())?;
+            seal_from(&mut records, last.seq + 1, &last.hash)?;
+            let mut file = io::BufWriter::new(fs::OpenOptions::new().append(true).open(path)?);
+            for record in &records {
+                serde_json::to_writer(&mut file, record)?;
+                file.write_all(b"\n")?;
+            }
+            file.flush()?;
+            file.get_ref().sync_all()?;
+        }
+        None => {
+            let (_, mut values) = if path.exists() {
+                crate::store::read_values(path)?
+            } else {
+                (crate::store::Layout::Lines, Vec::new())
+            };
+            // A sealed file ending in an unsealed record was changed outside of kioku
+            if is_sealed(&values) {
+                verify(path, None)?;
+            }
+            for record in values.iter_mut() {
+                crate::metadata::migrate_record(record)?;
+            }
+            values.append(&mut records);
+            seal(&mut values)?;
+            crate::store::write_values(path, crate::store::Layout::Lines, &values)?;
+        }
+    }
+    Ok(())
+}
+
+/// Record the changes of kioku to the current records of the sealed file `path`, from `before`
+/// to `after`. Earlier records are never rewritten: changed records are appended, superseding
+/// the earlier records of their runs, and removed runs are appended marked as removed. The chain
+/// is checked first, so records changed outside of kioku are never sealed over.
+pub fn append_changes(path: &Path, before: &[Value], after: &[Value]) -> anyhow::Result<()> {
+    verify(path, None).with_context(|| {
+        format!(
+            "Refusing to change {}, its seal is broken",
+            path.to_string_lossy()
+        )
+    })?;
+    let before_runs: HashMap<&str, &Value> = before
+        .iter()
+        .filter_map(|record| Some((run_key(record)?, record)))
+        .collect();
+    let after_runs: HashMap<&str, &Value> = after
+        .iter()
+        .filter_map(|record| Some((run_key(record)?, record)))
+        .collect();
+    let mut changes: Vec<Value> = after
+        .iter()
+        .filter(|record| run_key(record).and_then(|key| before_runs.get(key)) != Some(record))
+        .cloned()
+        .collect();
+    for record in before {
+        if let Some(key) = run_key(record)
+            && !after_runs.contains_key(key)
+            && let Value::Object(fields) = record
+        {
+            let mut removed = fields.clone();
+            removed.insert(REMOVED.to_string(), Value::Bool(true));
+            changes.push(Value::Object(removed));
+        }
+    }
+    if changes.is_empty() {
+        return Ok(());
+    }
+    append(path, &changes)
+}
+
+/// Outcome of verifying a sealed file
+pub struct Verified {
+    pub records: u64,
+    /// Hash of the last record, which later verifications can be checked against
+    pub head: String,
+    pub authenticated: bool,
+}
+
+fn broken(line: usize, reason: String) -> anyhow::Error {
+    KiokuErr::SealBroken { line, reason }.into()
+}
+
+/// Check the seals of the JSON lines file `path`, authenticating them if a key is configured.
+/// With `head`, the file must contain the record of that hash, so records dropped from the end
+/// are noticed too.
+pub fn verify(path: &Path, head: Option<&str>) -> anyhow::Result<Verified> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read metadata file {}", path.to_string_lossy()))?;
+    let mut prev = GENESIS.to_string();
+    let mut seq = 0;
+    let mut head_seen = head.is_none();
+    for (i, line) in contents.lines().enumerate() {
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Map<String, Value> = serde_json::from_str(line).map_err(|e| {
+            broken(
+                line_number,
+                format!("not a JSON object ({}), the file may be truncated", e),
+            )
+        })?;
+        let seal: Seal = record
+            .get(FIELD)
+            .cloned()
+            .ok_or_else(|| broken(line_number, "the record is not sealed".to_string()))
+            .and_then(|seal| {
+                serde_json::from_value(seal)
+                    .map_err(|e| broken(line_number, format!("invalid seal ({})", e)))
+            })?;
+        if seal.seq != seq {
+            return Err(broken(
+                line_number,
+                format!("expected record {}, found record {}", seq, seal.seq),
+            ));
+        }
+        if seal.prev != prev {
+            return Err(broken(
+                line_number,
+                "the record before was changed or removed".to_string(),
+            ));
+        }
+        if digest(&prev, &record)? != seal.hash {
+            return Err(broken(line_number, "the record was changed".to_string()));
+        }
+        if let Some(key) = key()
+            && seal.hmac.as_deref() != Some(authenticate(key, &seal.hash).as_str())
+        {
+            return Err(broken(
+                line_number,
+                "the seal was not made with the seal key".to_string(),
+            ));
+        }
+        head_seen = head_seen || head == Some(seal.hash.as_str());
+        prev = seal.hash;
+        seq += 1;
+    }
+    if !head_seen {
+        return Err(broken(
+            contents.lines().count(),
+            "the record with the expected head is missing, the file was truncated".to_string(),
+        ));
+    }
+    Ok(Verified {
+        records: seq,
+        head: prev,
+        authenticated: key().is_some(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_break_the_chain() {
+        let path = std::env::temp_dir().join(format!("kioku-seal-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let record = |label: &str| serde_json::json!({ "label": label, "loss": 0.32 });
+        append(&path, &[record("gene-ruin-note")]).unwrap();
+        append(
+            &path,
+            &[record("robe-speed-fake"), record("wedge-sash-fund")],
+        )
+        .unwrap();
+        let verified = verify(&path, None).unwrap();
+        assert_eq!(verified.records, 3);
+        assert!(verify(&path, Some(&verified.head)).is_ok());
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, contents.replace("0.32", "0.31")).unwrap();
+        assert!(verify(&path, None).is_err());
+        let truncated: Vec<&str> = contents.lines().take(2).collect();
+        fs::write(&path, truncated.join("\n")).unwrap();
+        assert!(verify(&path, None).is_ok());
+        assert!(verify(&path, Some(&verified.head)).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn keyed_and_unkeyed_seals_do_not_mix() {
+        let seal = |hmac: Option<&str>| Seal {
+            seq: 0,
+            prev: GENESIS.to_string(),
+            hash: GENESIS.to_string(),
+            hmac: hmac.map(str::to_string),
+        };
+        assert!(check_key(&seal(None), None).is_ok());
+        assert!(check_key(&seal(Some("ab")), Some("key")).is_ok());
+        assert!(check_key(&seal(Some("ab")), None).is_err());
+        assert!(check_key(&seal(None), Some("key")).is_err());
+    }
+
+    #[test]
+    fn updates_append_and_keep_tampering_visible() {
+        let path =
+            std::env::temp_dir().join(format!("kioku-seal-update-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut sink = crate::sink::JsonLines {
+            path: path.clone(),
+            sealed: true,
+        };
+        let records = crate::metadata::collect(
+            &["boil-envy-index".to_string(), "gene-ruin-note".to_string()],
+            &[],
+            &crate::metadata::Clock::default(),
+        );
+        crate::sink::MetadataSink::write(&mut sink, &records).unwrap();
+        let head = verify(&path, None).unwrap().head;
+
+        crate::store::update(&path, "boil-envy-index", |meta| {
+            meta.retag(&["+foo".to_string()]);
+            Ok(())
+        })
+        .unwrap();
+        let verified = verify(&path, Some(&head)).unwrap();
+        assert_eq!(verified.records, 3);
+        let current = crate::store::read_records(&path).unwrap();
+        assert_eq!(current.len(), 2);
+        assert_eq!(current[0].tags, ["foo"]);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(
+            &path,
+            contents.replacen("boil-envy-index", "boil-envy-inbox", 1),
+        )
+        .unwrap();
+        assert!(verify(&path, None).is_err());
+        assert!(
+            crate::store::update(&path, "gene-ruin-note", |meta| {
+                meta.retag(&["+bar".to_string()]);
+                Ok(())
+            })
+            .is_err()
+        );
+        assert!(verify(&path, None).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
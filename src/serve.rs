@@ -0,0 +1,195 @@
+use crate::metadata::MetaData;
+use crate::store;
+use std::path::Path;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Most names a single `POST /names` request can ask for
+pub const MAX_COUNT: usize = 10_000;
+
+/// Body of `POST /names`, all fields are optional
+#[derive(Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NameRequest {
+    pub count: Option<usize>,
+    pub length: Option<usize>,
+    pub tags: Vec<String>,
+    /// Reserve the names for this long instead of recording runs, as `kioku reserve --ttl`
+    pub ttl: Option<String>,
+}
+
+struct Reply {
+    status: u16,
+    body: serde_json::Value,
+}
+
+impl Reply {
+    fn ok(body: impl serde::Serialize) -> Reply {
+        match serde_json::to_value(body) {
+            Ok(body) => Reply { status: 200, body },
+            Err(e) => Reply::error(500, &e.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Reply {
+        Reply {
+            status,
+            body: serde_json::json!({ "error": message }),
+        }
+    }
+}
+
+/// Decode the percent-encoded bytes of a URL path segment as UTF-8
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// The label or run id addressed by a `/runs/<slug>` path
+fn slug(path: &str) -> Result<String, Reply> {
+    percent_decode(&path["/runs/".len()..])
+        .ok_or_else(|| Reply::error(400, "Invalid percent-encoding in the run name"))
+}
+
+fn read_runs(store: Option<&Path>) -> Result<Vec<MetaData>, Reply> {
+    let store = store.ok_or_else(|| {
+        Reply::error(
+            404,
+            "No metadata file configured, start the server with --output",
+        )
+    })?;
+    if !store.exists() {
+        return Ok(Vec::new());
+    }
+    store::read_records(store).map_err(|e| Reply::error(500, &format!("{:#}", e)))
+}
+
+fn handle(
+    request: &mut Request,
+    store: Option<&Path>,
+    generate: &mut impl FnMut(NameRequest) -> anyhow::Result<Vec<MetaData>>,
+) -> Reply {
+    let url = request.url().to_string();
+    let path = url.split('?').next().unwrap_or_default();
+    match (request.method(), path) {
+        (Method::Post, "/names") => {
+            let mut body = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return Reply::error(400, &e.to_string());
+            }
+            let name_request = if body.trim().is_empty() {
+                Ok(NameRequest::default())
+            } else {
+                serde_json::from_str(&body)
+            };
+            match name_request {
+                Ok(NameRequest {
+                    count: Some(count), ..
+                }) if count > MAX_COUNT => Reply::error(
+                    400,
+                    &format!("At most {} names can be requested at once", MAX_COUNT),
+                ),
+                Ok(name_request) => generate(name_request)
+                    .map(Reply::ok)
+                    .unwrap_or_else(|e| Reply::error(422, &format!("{:#}", e))),
+                Err(e) => Reply::error(400, &format!("Invalid request: {}", e)),
+            }
+        }
+        (Method::Get, "/runs") => read_runs(store).map(Reply::ok).unwrap_or_else(|e| e),
+        (Method::Get, _) if path.starts_with("/runs/") => {
+            let slug = match slug(path) {
+                Ok(slug) => slug,
+                Err(reply) => return reply,
+            };
+            let slug = slug.as_str();
+            match read_runs(store) {
+                Ok(runs) => runs
+                    .iter()
+                    .rev()
+                    .find(|meta| meta.is(slug))
+                    .or_else(|| {
+                        runs.iter()
+                            .rev()
+                            .find(|meta| meta.aliases.iter().any(|alias| alias == slug))
+                    })
+                    .map(Reply::ok)
+                    .unwrap_or_else(|| Reply::error(404, &format!("No run named {}", slug))),
+                Err(reply) => reply,
+            }
+        }
+        (Method::Delete, _) if path.starts_with("/runs/") => {
+            let slug = match slug(path) {
+                Ok(slug) => slug,
+                Err(reply) => return reply,
+            };
+            match store {
+                Some(store) => crate::store::release(store, &slug)
+                    .map(Reply::ok)
+                    .unwrap_or_else(|e| Reply::error(409, &format!("{:#}", e))),
+                None => Reply::error(
+                    404,
+                    "No metadata file configured, start the server with --output",
+                ),
+            }
+        }
+        (_, "/names" | "/runs") => Reply::error(405, "Method not allowed"),
+        _ => Reply::error(404, "Not found"),
+    }
+}
+
+/// Answer requests on `address` until the process is stopped.
+/// Requests are handled one at a time, so writes to the store never interleave.
+pub fn serve(
+    address: &str,
+    store: Option<&Path>,
+    mut generate: impl FnMut(NameRequest) -> anyhow::Result<Vec<MetaData>>,
+) -> anyhow::Result<()> {
+    let server = Server::http(address)
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", address, e))?;
+    log::info!("Listening on http://{}", address);
+    let content_type = Header::from_bytes("Content-Type", "application/json")
+        .map_err(|_| anyhow::anyhow!("Invalid header"))?;
+    for mut request in server.incoming_requests() {
+        let reply = handle(&mut request, store, &mut generate);
+        let url = request.url().to_string();
+        log::debug!("{} {} {}", request.method(), url, reply.status);
+        let response = Response::from_string(format!("{}\n", reply.body))
+            .with_status_code(reply.status)
+            .with_header(content_type.clone());
+        if let Err(e) = request.respond(response) {
+            log::warn!("Failed to respond to {}: {}", url, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_are_percent_decoded() {
+        assert_eq!(
+            percent_decode("vision%2F2026-W24%2Fbold-otter").as_deref(),
+            Some("vision/2026-W24/bold-otter")
+        );
+        assert_eq!(
+            percent_decode("%E3%81%95%E3%81%8F%E3%82%89").as_deref(),
+            Some("さくら")
+        );
+        assert_eq!(percent_decode("plain").as_deref(), Some("plain"));
+        assert!(percent_decode("bad%2").is_none());
+        assert!(percent_decode("bad%zz").is_none());
+        assert!(percent_decode("%ff").is_none());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::config::Config;
+use crate::error::{KiokuErr, TypedContext};
+use crate::metadata::{MetaData, append_csv, with_lock, write_atomic, write_records};
+use crate::remote;
+use anyhow::Context;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// `--output` value writing the records to stdout
+pub const STDOUT: &str = "-";
+
+/// Destination the metadata of new runs is written to
+pub trait MetadataSink {
+    /// Record new runs, or the new state of runs recorded before
+    fn write(&mut self, records: &[MetaData]) -> anyhow::Result<()>;
+
+    /// The local file holding the records, which later updates of the runs rewrite.
+    /// Sinks without one are append only.
+    fn file(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Seal the records written from now on, see `crate::seal`
+    fn seal(&mut self) -> anyhow::Result<()> {
+        anyhow::bail!("Only JSON lines metadata files can be sealed")
+    }
+}
+
+/// Pick the sink for `--output` by its scheme or extension: `-` is stdout, `ssh://`, `http://`
+/// and `https://` are remote logs, `sqlite://` or a `.sqlite`, `.sqlite3` or `.db` extension a
+/// SQLite database, `.csv` and `.jsonl` append to a file, and anything else is a JSON file that
+/// gets the `.json` extension if it has none.
+pub fn open<'a>(output: &str, config: &'a Config) -> Box<dyn MetadataSink + 'a> {
+    if output == STDOUT {
+        return Box::new(Stdout);
+    }
+    if output.starts_with("ssh://") {
+        return Box::new(Ssh(output.to_string()));
+    }
+    if output.starts_with("http://") || output.starts_with("https://") {
+        return Box::new(Http {
+            url: output.to_string(),
+            config,
+        });
+    }
+    if let Some(path) = output.strip_prefix("sqlite://") {
+        return Box::new(Sqlite(PathBuf::from(path)));
+    }
+    let path = PathBuf::from(output);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("sqlite" | "sqlite3" | "db") => Box::new(Sqlite(path)),
+        Some("csv") => Box::new(Csv(path)),
+        Some("jsonl") => Box::new(JsonLines {
+            path,
+            sealed: false,
+        }),
+        Some("json") => Box::new(JsonFile(path)),
+        _ => Box::new(JsonFile(PathBuf::from(format!("{}.json", output)))),
+    }
+}
+
+/// Run `write` holding the lock on the metadata file `path`, reporting failures as
+/// `KiokuErr::MetadataWrite`
+fn write_locked(path: &Path, write: impl FnOnce() -> anyhow::Result<()>) -> anyhow::Result<()> {
+    log::debug!("Writing metadata to {}", path.to_string_lossy());
+    with_lock(path, write).map_err(|source| {
+        KiokuErr::MetadataWrite {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        }
+        .into()
+    })
+}
+
+/// A pretty printed JSON file holding an object for a single record or an array of records,
+/// replaced with every write
+pub struct JsonFile(pub PathBuf);
+
+impl MetadataSink for JsonFile {
+    fn write(&mut self, records: &[MetaData]) -> anyhow::Result<()> {
+        write_locked(&self.0, || {
+            write_atomic(&self.0, |writer| write_records(writer, records, false))
+        })
+    }
+
+    fn file(&self) -> Option<&Path> {
+        Some(&self.0)
+    }
+}
+
+/// A JSON lines file the records are appended to
+pub struct JsonLines {
+    pub path: PathBuf,
+    /// Seal the records even if the file is not sealed yet
+    pub sealed: bool,
+}
+
+impl MetadataSink for JsonLines {
+    fn write(&mut self, records: &[MetaData]) -> anyhow::Result<()> {
+        write_locked(&self.path, || {
+            // Files stay sealed once they are
+            if self.sealed || crate::seal::last_seal(&self.path)?.is_some() {
+                let values = records
+                    .iter()
+                    .map(serde_json::to_value)
+                    .collect::<Result<Vec<_>, _>>()
+                    .typed_context("Failed to serialize metadata")?;
+                return crate::seal::append(&self.path, &values);
+            }
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .with_typed_context(|| format!("Failed to open {}", self.path.to_string_lossy()))?;
+            write_records(&mut io::BufWriter::new(file), records, true)
+        })
+    }
+
+    fn file(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn seal(&mut self) -> anyhow::Result<()> {
+        self.sealed = true;
+        Ok(())
+    }
+}
+
+/// A CSV file the records are appended to as rows
+pub struct Csv(pub PathBuf);
+
+impl MetadataSink for Csv {
+    fn write(&mut self, records: &[MetaData]) -> anyhow::Result<()> {
+        write_locked(&self.0, || append_csv(&self.0, records))
+    }
+
+    fn file(&self) -> Option<&Path> {
+        Some(&self.0)
+    }
+}
+
+/// A SQLite database with a `runs` table keyed by run id. Records written again, such as the
+/// finished record of `kioku run`, replace the earlier one.
+pub struct Sqlite(pub PathBuf);
+
+impl MetadataSink for Sqlite {
+    fn write(&mut self, records: &[MetaData]) -> anyhow::Result<()> {
+        write_locked(&self.0, || {
+            let mut db = crate::store::open_sqlite(&self.0)?;
+            let tx = db.transaction()?;
+            for meta in records {
+                let record =
+                    serde_json::to_value(meta).typed_context("Failed to serialize metadata")?;
+                crate::store::insert_sqlite(&tx, &record)?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn file(&self) -> Option<&Path> {
+        Some(&self.0)
+    }
+}
+
+/// A shared log on another machine, appended to over ssh
+pub struct Ssh(pub String);
+
+impl MetadataSink for Ssh {
+    fn write(&mut self, records: &[MetaData]) -> anyhow::Result<()> {
+        log::debug!("Appending metadata to {}", self.0);
+        remote::append_ssh(&self.0, &remote::jsonl(records)?)
+            .with_context(|| format!("Failed to append metadata to {}", self.0))
+    }
+}
+
+/// A shared log on a server, appended to by POSTing the records as JSON lines
+pub struct Http<'a> {
+    pub url: String,
+    pub config: &'a Config,
+}
+
+impl MetadataSink for Http<'_> {
+    fn write(&mut self, records: &[MetaData]) -> anyhow::Result<()> {
+        log::debug!("Appending metadata to {}", self.url);
+        remote::append_http(&self.url, remote::jsonl(records)?, self.config)
+            .with_context(|| format!("Failed to append metadata to {}", self.url))
+    }
+}
+
+/// Standard output, one JSON line per record
+pub struct Stdout;
+
+impl MetadataSink for Stdout {
+    fn write(&mut self, records: &[MetaData]) -> anyhow::Result<()> {
+        let mut stdout = io::stdout().lock();
+        write_records(&mut stdout, records, true)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{Clock, collect};
+
+    #[test]
+    fn jsonl_appends_one_record_per_line() {
+        let path = std::env::temp_dir().join(format!("kioku-test-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let config = Config::default();
+        let mut sink = open(&path.to_string_lossy(), &config);
+        let first = collect(&["gene-ruin-note".to_string()], &[], &Clock::default());
+        let rest = collect(
+            &["robe-speed-fake".to_string(), "wedge-sash-fund".to_string()],
+            &[],
+            &Clock::default(),
+        );
+        sink.write(&first).unwrap();
+        sink.write(&rest).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let labels: Vec<String> = contents
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                record["label"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            ["gene-ruin-note", "robe-speed-fake", "wedge-sash-fund"]
+        );
+    }
+
+    #[test]
+    fn sqlite_replaces_rewritten_runs() {
+        let path = std::env::temp_dir().join(format!("kioku-test-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let config = Config::default();
+        let mut sink = open(&path.to_string_lossy(), &config);
+        assert_eq!(sink.file(), Some(path.as_path()));
+        let mut records = collect(
+            &["gene-ruin-note".to_string(), "robe-speed-fake".to_string()],
+            &[],
+            &Clock::default(),
+        );
+        sink.write(&records).unwrap();
+        records[0].status = Some(crate::metadata::Status::Success);
+        sink.write(&records[..1]).unwrap();
+
+        let db = rusqlite::Connection::open(&path).unwrap();
+        let rows: Vec<(String, Option<String>)> = db
+            .prepare("SELECT label, status FROM runs ORDER BY label")
+            .unwrap()
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        drop(db);
+        assert_eq!(
+            rows,
+            [
+                ("gene-ruin-note".to_string(), Some("success".to_string())),
+                ("robe-speed-fake".to_string(), None)
+            ]
+        );
+
+        crate::store::update(&path, "robe-speed-fake", |meta| {
+            meta.retag(&["+baseline".to_string()]);
+            Ok(())
+        })
+        .unwrap();
+        crate::store::rename(&path, "gene-ruin-note", "gene-ruin-nose").unwrap();
+        let records = crate::store::read_records(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let labels: Vec<&str> = records.iter().map(|meta| meta.label.as_str()).collect();
+        assert_eq!(labels, ["gene-ruin-nose", "robe-speed-fake"]);
+        assert_eq!(records[1].tags, ["baseline"]);
+    }
+}
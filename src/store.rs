@@ -0,0 +1,525 @@
+use crate::metadata::{MetaData, Status, migrate_record, with_lock, write_atomic};
+use anyhow::{Context, bail};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// How the records of a metadata file are laid out
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// A single pretty printed JSON object
+    Object,
+    /// A pretty printed JSON array of objects
+    Array,
+    /// One JSON object per line
+    Lines,
+    /// Comma separated values with a header row
+    Csv,
+    /// A SQLite database with a `runs` table
+    Sqlite,
+}
+
+impl Layout {
+    pub fn of(path: &Path) -> Layout {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") => Layout::Lines,
+            Some("csv") => Layout::Csv,
+            Some("sqlite" | "sqlite3" | "db") => Layout::Sqlite,
+            _ => Layout::Object,
+        }
+    }
+}
+
+/// Header of SQLite databases, which `sqlite://` outputs may hold under any name
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+fn is_sqlite(path: &Path) -> bool {
+    let mut header = [0; SQLITE_HEADER.len()];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| header == SQLITE_HEADER)
+}
+
+/// Open a SQLite metadata database, creating its `runs` table keyed by run id if needed
+pub fn open_sqlite(path: &Path) -> anyhow::Result<rusqlite::Connection> {
+    let db = rusqlite::Connection::open(path)?;
+    db.busy_timeout(std::time::Duration::from_secs(30))?;
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS runs (
+            run_id TEXT PRIMARY KEY,
+            label TEXT NOT NULL,
+            timestamp TEXT NOT NULL,
+            status TEXT,
+            record TEXT NOT NULL
+        )",
+        (),
+    )?;
+    Ok(db)
+}
+
+/// Insert a record into a SQLite metadata database, replacing the earlier record of its run in
+/// place so runs keep the order they were recorded in
+pub fn insert_sqlite(db: &rusqlite::Connection, record: &Value) -> anyhow::Result<()> {
+    let field = |key: &str| record.get(key).and_then(Value::as_str);
+    let label = field("label").context("Metadata record without a label")?;
+    db.execute(
+        "INSERT INTO runs (run_id, label, timestamp, status, record)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT (run_id) DO UPDATE SET label = excluded.label,
+            timestamp = excluded.timestamp, status = excluded.status, record = excluded.record",
+        (
+            field("run_id").unwrap_or(label),
+            label,
+            field("timestamp").unwrap_or_default(),
+            field("status"),
+            serde_json::to_string(record)?,
+        ),
+    )?;
+    Ok(())
+}
+
+fn read_sqlite(path: &Path) -> anyhow::Result<Vec<Value>> {
+    let db = open_sqlite(path)?;
+    let mut query = db.prepare("SELECT record FROM runs ORDER BY rowid")?;
+    let rows = query.query_map((), |row| row.get::<_, String>(0))?;
+    let mut records = Vec::new();
+    for row in rows {
+        records.push(serde_json::from_str(&row?)?);
+    }
+    Ok(records)
+}
+
+fn write_sqlite(path: &Path, records: &[Value]) -> anyhow::Result<()> {
+    let mut db = open_sqlite(path)?;
+    let tx = db.transaction()?;
+    tx.execute("DELETE FROM runs", ())?;
+    for record in records {
+        insert_sqlite(&tx, record)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Fields of `MetaData` holding strings, kept as written even where they look like JSON numbers
+/// or booleans, such as the label `12345` or a timestamp formatted with `%s`
+const STRING_FIELDS: &[&str] = &[
+    "label",
+    "run_id",
+    "namespace",
+    "prefix",
+    "revision",
+    "timestamp",
+    "directory",
+    "status",
+    "finished",
+    "reserved_until",
+    "stdout",
+    "stderr",
+    "cloned_from",
+];
+
+fn csv_cell(key: &str, cell: &str) -> Value {
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    if STRING_FIELDS.contains(&key) {
+        return Value::String(cell.to_string());
+    }
+    match serde_json::from_str::<Value>(cell) {
+        Ok(value @ (Value::Number(_) | Value::Array(_) | Value::Object(_) | Value::Bool(_))) => {
+            value
+        }
+        _ => Value::String(cell.to_string()),
+    }
+}
+
+/// Read the rows of a CSV file with a header row as records, reading cells of fields other than
+/// the string fields of `MetaData` as JSON where possible
+pub fn read_csv(path: &Path) -> anyhow::Result<Vec<Value>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let header = reader.headers()?.clone();
+    reader
+        .records()
+        .map(|row| {
+            let row = row?;
+            Ok(Value::Object(
+                header
+                    .iter()
+                    .zip(row.iter())
+                    .map(|(k, v)| (k.to_string(), csv_cell(k, v)))
+                    .collect(),
+            ))
+        })
+        .collect()
+}
+
+/// Read all records of a metadata file along with the layout they were stored in.
+/// JSON lines files written by older versions contained pretty printed records spanning several
+/// lines, so these are read as a stream of JSON values rather than line by line.
+pub fn read_values(path: &Path) -> anyhow::Result<(Layout, Vec<Value>)> {
+    let layout = Layout::of(path);
+    if layout == Layout::Sqlite || is_sqlite(path) {
+        let values = read_sqlite(path)
+            .with_context(|| format!("Failed to read metadata file {}", path.to_string_lossy()))?;
+        return Ok((Layout::Sqlite, values));
+    }
+    if layout == Layout::Csv {
+        let values = read_csv(path)
+            .with_context(|| format!("Failed to read metadata file {}", path.to_string_lossy()))?;
+        return Ok((layout, values));
+    }
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read metadata file {}", path.to_string_lossy()))?;
+    let values = serde_json::Deserializer::from_str(&contents)
+        .into_iter::<Value>()
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse metadata file {}", path.to_string_lossy()))?;
+    match (layout, values.as_slice()) {
+        (Layout::Lines, _) => Ok((layout, values)),
+        (_, [Value::Array(records)]) => Ok((Layout::Array, records.clone())),
+        (_, [Value::Object(_)]) => Ok((Layout::Object, values)),
+        _ => bail!(
+            "Metadata file {} does not contain a JSON object or array",
+            path.to_string_lossy()
+        ),
+    }
+}
+
+fn write_csv(writer: &mut impl Write, records: &[Value]) -> anyhow::Result<()> {
+    let mut header: Vec<&String> = Vec::new();
+    for record in records {
+        for key in record.as_object().into_iter().flat_map(Map::keys) {
+            if !header.contains(&key) {
+                header.push(key);
+            }
+        }
+    }
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(&header)?;
+    for record in records {
+        writer.write_record(header.iter().map(|k| match record.get(k.as_str()) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+        }))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Atomically replace the records of a metadata file
+pub fn write_values(path: &Path, layout: Layout, records: &[Value]) -> anyhow::Result<()> {
+    let written = if layout == Layout::Sqlite {
+        write_sqlite(path, records)
+    } else {
+        write_atomic(path, |writer| write_file(writer, layout, records))
+    };
+    written.map_err(|source| {
+        crate::error::KiokuErr::MetadataWrite {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        }
+        .into()
+    })
+}
+
+fn write_file(writer: &mut impl Write, layout: Layout, records: &[Value]) -> anyhow::Result<()> {
+    match (layout, records) {
+        (Layout::Lines, _) => {
+            for record in records {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all("\n".as_bytes())?;
+            }
+        }
+        (Layout::Csv, _) => write_csv(writer, records)?,
+        (Layout::Object, [record]) => {
+            serde_json::to_writer_pretty(&mut *writer, record)?;
+            writer.write_all("\n".as_bytes())?;
+        }
+        (Layout::Object | Layout::Array, _) => {
+            serde_json::to_writer_pretty(&mut *writer, records)?;
+            writer.write_all("\n".as_bytes())?;
+        }
+        (Layout::Sqlite, _) => bail!("SQLite databases are not written as text"),
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read all records of a metadata file, upgraded to the current schema. Of sealed files only
+/// the current records are read, see `crate::seal::current`.
+pub fn read(path: &Path) -> anyhow::Result<(Layout, Vec<Value>)> {
+    let (layout, mut records) = read_values(path)?;
+    for record in records.iter_mut() {
+        migrate_record(record)?;
+    }
+    if layout == Layout::Lines && crate::seal::is_sealed(&records) {
+        records = crate::seal::current(records);
+    }
+    Ok((layout, records))
+}
+
+/// Replace the records `before` read from a metadata file with `after`. Sealed JSON lines files
+/// are not rewritten, the changes are appended to them instead.
+pub fn save(path: &Path, layout: Layout, before: &[Value], after: &[Value]) -> anyhow::Result<()> {
+    if layout == Layout::Lines && crate::seal::is_sealed_file(path)? {
+        crate::seal::append_changes(path, before, after)
+    } else {
+        write_values(path, layout, after)
+    }
+}
+
+/// Read all records of a metadata file as typed metadata
+pub fn read_records(path: &Path) -> anyhow::Result<Vec<MetaData>> {
+    read(path)?
+        .1
+        .into_iter()
+        .map(|record| {
+            serde_json::from_value(record).with_context(|| {
+                format!("Invalid metadata record in file {}", path.to_string_lossy())
+            })
+        })
+        .collect()
+}
+
+/// Index of the record `slug` refers to: the most recent record labelled `slug` or with run id
+/// `slug`, otherwise the most recent record that was renamed from `slug`
+fn position(records: &[Value], slug: &str) -> Option<usize> {
+    let has = |record: &Value, key: &str| record.get(key).and_then(Value::as_str) == Some(slug);
+    records
+        .iter()
+        .rposition(|record| has(record, "label") || has(record, "run_id"))
+        .or_else(|| {
+            records.iter().rposition(|record| {
+                record
+                    .get("aliases")
+                    .and_then(Value::as_array)
+                    .is_some_and(|aliases| aliases.iter().any(|alias| alias == slug))
+            })
+        })
+}
+
+fn not_found(path: &Path, slug: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "No run labelled {} in metadata file {}",
+        slug,
+        path.to_string_lossy()
+    )
+}
+
+/// The record `slug` refers to, by label, run id or former label
+pub fn find(path: &Path, slug: &str) -> anyhow::Result<MetaData> {
+    let mut records = read(path)?.1;
+    let i = position(&records, slug).ok_or_else(|| not_found(path, slug))?;
+    serde_json::from_value(records.swap_remove(i))
+        .with_context(|| format!("Invalid metadata record for {}", slug))
+}
+
+/// Add records to a metadata file, creating it if needed
+pub fn append(path: &Path, records: &[MetaData]) -> anyhow::Result<()> {
+    with_lock(path, || {
+        let (layout, before) = if path.exists() {
+            read(path)?
+        } else {
+            (Layout::of(path), Vec::new())
+        };
+        let mut values = before.clone();
+        for meta in records {
+            values.push(serde_json::to_value(meta)?);
+        }
+        save(path, layout, &before, &values)
+    })
+}
+
+/// Reserve names in a metadata file: `reserve` is given the labels and former labels of all
+/// runs and live reservations and returns the reservations to add. Expired reservations are
+/// dropped. The file stays locked throughout, so concurrent reservations never pick the same name.
+pub fn reserve(
+    path: &Path,
+    reserve: impl FnOnce(&HashSet<String>) -> anyhow::Result<Vec<MetaData>>,
+) -> anyhow::Result<Vec<MetaData>> {
+    with_lock(path, || {
+        let (layout, before) = if path.exists() {
+            read(path)?
+        } else {
+            (Layout::of(path), Vec::new())
+        };
+        let mut values = before.clone();
+        let now = chrono::Utc::now();
+        values.retain(|record| {
+            serde_json::from_value::<MetaData>(record.clone()).map_or(true, |meta| {
+                meta.status != Some(Status::Reserved) || meta.holds_reservation(now)
+            })
+        });
+        let mut taken = HashSet::new();
+        for record in &values {
+            taken.extend(
+                record
+                    .get("label")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            );
+            let aliases = record.get("aliases").and_then(Value::as_array);
+            taken.extend(
+                aliases
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string),
+            );
+        }
+        let reserved = reserve(&taken)?;
+        for meta in &reserved {
+            values.push(serde_json::to_value(meta)?);
+        }
+        save(path, layout, &before, &values)?;
+        Ok(reserved)
+    })
+}
+
+/// Record runs named elsewhere. The reservation of a name is replaced by its record in place, and
+/// names of recorded runs are refused unless `force` is given. The file stays locked throughout,
+/// so no reservation of the names can be taken in between.
+pub fn register(path: &Path, records: &[MetaData], force: bool) -> anyhow::Result<()> {
+    with_lock(path, || {
+        let (layout, before) = if path.exists() {
+            read(path)?
+        } else {
+            (Layout::of(path), Vec::new())
+        };
+        let mut values = before.clone();
+        for meta in records {
+            let value = serde_json::to_value(meta)?;
+            let Some(i) = position(&values, &meta.label) else {
+                values.push(value);
+                continue;
+            };
+            let found: MetaData = serde_json::from_value(values[i].clone())
+                .with_context(|| format!("Invalid metadata record for {}", meta.label))?;
+            if found.status == Some(Status::Reserved) {
+                values[i] = value;
+            } else if force {
+                values.push(value);
+            } else {
+                bail!(
+                    "{} is already recorded in {}, pass --force to record it again",
+                    meta.label,
+                    path.to_string_lossy()
+                );
+            }
+        }
+        save(path, layout, &before, &values)
+    })
+}
+
+/// Remove the reservation `slug` refers to, by label or run id, returning it
+pub fn release(path: &Path, slug: &str) -> anyhow::Result<MetaData> {
+    with_lock(path, || {
+        let (layout, before) = read(path)?;
+        let mut records = before.clone();
+        let i = position(&records, slug).ok_or_else(|| not_found(path, slug))?;
+        let meta: MetaData = serde_json::from_value(records[i].clone())
+            .with_context(|| format!("Invalid metadata record for {}", slug))?;
+        if meta.status != Some(Status::Reserved) {
+            bail!("{} is a recorded run, not a reservation", slug);
+        }
+        records.remove(i);
+        save(path, layout, &before, &records)?;
+        Ok(meta)
+    })
+}
+
+/// Apply `update` to the record `slug` refers to, by label, run id or former label, and write
+/// the file back
+pub fn update(
+    path: &Path,
+    slug: &str,
+    update: impl FnOnce(&mut MetaData) -> anyhow::Result<()>,
+) -> anyhow::Result<MetaData> {
+    with_lock(path, || {
+        let (layout, before) = read(path)?;
+        let mut records = before.clone();
+        let i = position(&records, slug).ok_or_else(|| not_found(path, slug))?;
+        let record = &mut records[i];
+        let mut meta: MetaData = serde_json::from_value(record.take())
+            .with_context(|| format!("Invalid metadata record for {}", slug))?;
+        update(&mut meta)?;
+        *record = serde_json::to_value(&meta)?;
+        save(path, layout, &before, &records)?;
+        Ok(meta)
+    })
+}
+
+/// Relabel the run `slug` refers to as `new`, keeping its former label as an alias
+pub fn rename(path: &Path, slug: &str, new: &str) -> anyhow::Result<MetaData> {
+    with_lock(path, || {
+        let (layout, before) = read(path)?;
+        let mut records = before.clone();
+        let i = position(&records, slug).ok_or_else(|| not_found(path, slug))?;
+        // Renaming a run back to one of its former labels is fine
+        if position(&records, new).is_some_and(|j| j != i) {
+            bail!(
+                "{} already names a run in metadata file {}",
+                new,
+                path.to_string_lossy()
+            );
+        }
+        let mut meta: MetaData = serde_json::from_value(records[i].take())
+            .with_context(|| format!("Invalid metadata record for {}", slug))?;
+        meta.rename(new);
+        records[i] = serde_json::to_value(&meta)?;
+        save(path, layout, &before, &records)?;
+        Ok(meta)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{Clock, append_csv, collect};
+
+    #[test]
+    fn csv_strings_stay_strings() {
+        let path = std::env::temp_dir().join(format!("kioku-store-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut records = collect(&["12345".to_string()], &[], &Clock::default());
+        records[0].timestamp = "1768462809".to_string();
+        records[0].revision = Some("true".to_string());
+        records[0].extra.insert("lr".to_string(), 0.1.into());
+        append_csv(&path, &records).unwrap();
+
+        let records = read_records(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(records[0].label, "12345");
+        assert_eq!(records[0].timestamp, "1768462809");
+        assert_eq!(records[0].revision.as_deref(), Some("true"));
+        assert_eq!(records[0].extra["lr"], 0.1);
+    }
+
+    #[test]
+    fn registering_claims_reservations() {
+        let path =
+            std::env::temp_dir().join(format!("kioku-register-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let clock = Clock::default();
+        let names = ["gene-ruin-note".to_string(), "robe-speed-fake".to_string()];
+        let mut reserved = collect(&names, &[], &clock);
+        reserved[0].status = Some(Status::Reserved);
+        append(&path, &reserved).unwrap();
+
+        register(&path, &collect(&names[..1], &[], &clock), false).unwrap();
+        let error = register(&path, &collect(&names[1..], &[], &clock), false).unwrap_err();
+        assert!(error.to_string().contains("--force"), "{}", error);
+        register(&path, &collect(&names[1..], &[], &clock), true).unwrap();
+        let records = read_records(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let labels: Vec<&str> = records.iter().map(|meta| meta.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["gene-ruin-note", "robe-speed-fake", "robe-speed-fake"]
+        );
+        assert!(records[0].status.is_none());
+    }
+}
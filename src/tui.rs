@@ -0,0 +1,312 @@
+use crate::metadata::{Clock, MetaData, Status};
+use crate::store;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::{cursor, execute, terminal};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame, Terminal};
+use std::io::{self, Stderr};
+use std::path::Path;
+
+#[derive(PartialEq)]
+enum Mode {
+    Browse,
+    Filter,
+    Tag,
+    Note,
+}
+
+struct App<'a> {
+    path: &'a Path,
+    /// Reads the timestamps the filter compares dates with
+    clock: &'a Clock,
+    records: Vec<MetaData>,
+    /// Indices of the records passing the filter
+    visible: Vec<usize>,
+    state: ListState,
+    filter: String,
+    mode: Mode,
+    input: String,
+    message: String,
+}
+
+impl App<'_> {
+    fn reload(&mut self) -> anyhow::Result<()> {
+        let selected = self.selected().map(|meta| meta.label.clone());
+        self.records = store::read_records(self.path)?;
+        self.refilter();
+        if let Some(label) = selected
+            && let Some(i) = self
+                .visible
+                .iter()
+                .position(|&i| self.records[i].label == label)
+        {
+            self.state.select(Some(i));
+        }
+        Ok(())
+    }
+
+    fn refilter(&mut self) {
+        self.visible = (0..self.records.len())
+            .filter(|&i| self.records[i].matches(&self.filter, self.clock))
+            .collect();
+        self.state.select(if self.visible.is_empty() {
+            None
+        } else {
+            Some(
+                self.state
+                    .selected()
+                    .unwrap_or(0)
+                    .min(self.visible.len() - 1),
+            )
+        });
+    }
+
+    fn selected(&self) -> Option<&MetaData> {
+        self.state
+            .selected()
+            .and_then(|i| self.visible.get(i))
+            .map(|&i| &self.records[i])
+    }
+
+    /// Apply the tag changes or note typed for the selected run to the store
+    fn submit(&mut self) -> anyhow::Result<()> {
+        let Some(meta) = self.selected() else {
+            return Ok(());
+        };
+        let label = meta.label.clone();
+        // The run id tells apart runs sharing a label
+        let id = meta.run_id.clone().unwrap_or_else(|| label.clone());
+        let input = std::mem::take(&mut self.input);
+        match self.mode {
+            Mode::Tag => {
+                let changes: Vec<String> = input.split_whitespace().map(str::to_string).collect();
+                store::update(self.path, &id, |meta| {
+                    meta.retag(&changes);
+                    Ok(())
+                })?;
+                self.message = format!("Updated tags of {}", label);
+            }
+            Mode::Note if !input.trim().is_empty() => {
+                store::update(self.path, &id, |meta| {
+                    meta.add_note(input.trim());
+                    Ok(())
+                })?;
+                self.message = format!("Added note to {}", label);
+            }
+            _ => {}
+        }
+        self.reload()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list, detail] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let meta = &self.records[i];
+                let style = match meta.status {
+                    Some(Status::Running) => Style::new().yellow(),
+                    Some(Status::Failed) => Style::new().red(),
+                    Some(Status::Success) => Style::new().green(),
+                    Some(Status::Reserved) => Style::new().cyan(),
+                    None => Style::new(),
+                };
+                ListItem::new(meta.label.as_str()).style(style)
+            })
+            .collect();
+        let title = format!("Runs {}/{}", self.visible.len(), self.records.len());
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(Style::new().reversed()),
+            list,
+            &mut self.state,
+        );
+        let details = self
+            .selected()
+            .and_then(|meta| serde_json::to_string_pretty(meta).ok())
+            .unwrap_or_default();
+        frame.render_widget(
+            Paragraph::new(details)
+                .block(Block::bordered().title("Metadata"))
+                .wrap(Wrap { trim: false }),
+            detail,
+        );
+        let line = match self.mode {
+            Mode::Browse if self.message.is_empty() => {
+                Line::from("q quit  j/k move  / filter  t tag (+TAG -TAG)  n note  r reload".dim())
+            }
+            Mode::Browse => Line::from(self.message.as_str()),
+            Mode::Filter => Line::from(format!("Filter: {}", self.filter)),
+            Mode::Tag => Line::from(format!("Tags: {}", self.input)),
+            Mode::Note => Line::from(format!("Note: {}", self.input)),
+        };
+        frame.render_widget(Paragraph::new(line), status);
+    }
+
+    /// Handle a key press, returns whether to quit
+    fn key(&mut self, code: KeyCode) -> anyhow::Result<bool> {
+        match self.mode {
+            Mode::Browse => {
+                self.message.clear();
+                match code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+                    KeyCode::Char('j') | KeyCode::Down => self.state.select_next(),
+                    KeyCode::Char('k') | KeyCode::Up => self.state.select_previous(),
+                    KeyCode::Char('g') | KeyCode::Home => self.state.select_first(),
+                    KeyCode::Char('G') | KeyCode::End => self.state.select_last(),
+                    KeyCode::Char('/') => self.mode = Mode::Filter,
+                    KeyCode::Char('t') if self.selected().is_some() => self.mode = Mode::Tag,
+                    KeyCode::Char('n') if self.selected().is_some() => self.mode = Mode::Note,
+                    KeyCode::Char('r') => self.reload()?,
+                    _ => {}
+                }
+            }
+            Mode::Filter => {
+                match code {
+                    KeyCode::Enter | KeyCode::Esc => self.mode = Mode::Browse,
+                    KeyCode::Backspace => {
+                        self.filter.pop();
+                    }
+                    KeyCode::Char(c) => self.filter.push(c),
+                    _ => {}
+                }
+                self.refilter();
+            }
+            Mode::Tag | Mode::Note => match code {
+                KeyCode::Enter => {
+                    if let Err(e) = self.submit() {
+                        self.message = format!("{:#}", e);
+                    }
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Esc => {
+                    self.input.clear();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Char(c) => self.input.push(c),
+                _ => {}
+            },
+        }
+        Ok(false)
+    }
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> anyhow::Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && app.key(key.code)?
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Browse the runs recorded in `path`
+pub fn run(path: &Path, clock: &Clock) -> anyhow::Result<()> {
+    let mut app = App {
+        path,
+        clock,
+        records: Vec::new(),
+        visible: Vec::new(),
+        state: ListState::default(),
+        filter: String::new(),
+        mode: Mode::Browse,
+        input: String::new(),
+        message: String::new(),
+    };
+    app.reload()?;
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+fn pick_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+    generate: &mut impl FnMut() -> anyhow::Result<Vec<String>>,
+    mut names: Vec<String>,
+) -> anyhow::Result<Option<String>> {
+    let mut state = ListState::default().with_selected(Some(0));
+    loop {
+        terminal.draw(|frame| {
+            let [list, help] =
+                Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+            let items: Vec<ListItem> = names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| ListItem::new(format!("{} {}", i + 1, name)))
+                .collect();
+            frame.render_stateful_widget(
+                List::new(items)
+                    .block(Block::bordered().title("Pick a name"))
+                    .highlight_style(Style::new().reversed()),
+                list,
+                &mut state,
+            );
+            frame.render_widget(
+                Paragraph::new(
+                    "enter pick  j/k move  1-9 pick by number  r regenerate  q cancel".dim(),
+                ),
+                help,
+            );
+        })?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(state.selected().and_then(|i| names.get(i)).cloned()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(None);
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+            KeyCode::Char('j') | KeyCode::Down => state.select_next(),
+            KeyCode::Char('k') | KeyCode::Up => state.select_previous(),
+            KeyCode::Char(c @ '1'..='9') => {
+                if let Some(name) = names.get(c as usize - '1' as usize) {
+                    return Ok(Some(name.clone()));
+                }
+            }
+            KeyCode::Char('r') => {
+                names = generate()?;
+                state.select_first();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Let the user pick one of the names returned by `generate`, which is called again for new
+/// candidates. The prompt is drawn on stderr, so stdout only gets the picked name. Returns `None`
+/// if the user cancelled.
+pub fn pick(
+    mut generate: impl FnMut() -> anyhow::Result<Vec<String>>,
+) -> anyhow::Result<Option<String>> {
+    let names = generate()?;
+    terminal::enable_raw_mode()?;
+    let result = execute!(io::stderr(), terminal::EnterAlternateScreen)
+        .and_then(|()| Terminal::new(CrosstermBackend::new(io::stderr())))
+        .map_err(anyhow::Error::from)
+        .and_then(|mut terminal| pick_loop(&mut terminal, &mut generate, names));
+    let restored = execute!(io::stderr(), terminal::LeaveAlternateScreen, cursor::Show);
+    terminal::disable_raw_mode()?;
+    restored?;
+    result
+}
@@ -0,0 +1,91 @@
+use crate::metadata::MetaData;
+use anyhow::{Context, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use rand::Rng;
+use serde_json::json;
+use std::env;
+
+/// The W&B run registered for a kioku run
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct WandbRun {
+    pub entity: String,
+    pub project: String,
+    pub run_id: String,
+}
+
+static UPSERT_RUN: &str = "mutation UpsertBucket($name: String, $project: String, \
+$entity: String, $displayName: String, $commit: String, $tags: [String!]) {
+  upsertBucket(input: {name: $name, modelName: $project, entityName: $entity, \
+displayName: $displayName, commit: $commit, tags: $tags}) {
+    bucket { id name }
+  }
+}";
+
+fn base_url() -> String {
+    env::var("WANDB_BASE_URL").unwrap_or_else(|_| "https://api.wandb.ai".to_string())
+}
+
+/// Run ids in the style of the W&B client, names are only used for display
+fn run_id() -> String {
+    let mut rng = rand::rng();
+    (0..8)
+        .map(|_| {
+            let i = rng.random_range(0..36);
+            char::from_digit(i, 36).unwrap_or('0')
+        })
+        .collect()
+}
+
+/// Create a W&B run in `entity/project` displayed under the name of the record
+pub fn create_run(target: &str, meta: &MetaData) -> anyhow::Result<WandbRun> {
+    let Some((entity, project)) = target.split_once('/') else {
+        bail!(
+            "Invalid W&B project {}, expected <ENTITY>/<PROJECT>",
+            target
+        );
+    };
+    let api_key = env::var("WANDB_API_KEY").context("WANDB_API_KEY is not set")?;
+    let run = WandbRun {
+        entity: entity.to_string(),
+        project: project.to_string(),
+        run_id: run_id(),
+    };
+    let url = format!("{}/graphql", base_url().trim_end_matches('/'));
+    let body = json!({
+        "query": UPSERT_RUN,
+        "variables": {
+            "name": run.run_id,
+            "project": run.project,
+            "entity": run.entity,
+            "displayName": meta.label,
+            "commit": meta.revision,
+            "tags": meta.tags,
+        },
+    });
+    let response = ureq::post(&url)
+        .header("Content-Type", "application/json")
+        .header(
+            "Authorization",
+            format!("Basic {}", STANDARD.encode(format!("api:{}", api_key))),
+        )
+        .send(body.to_string())
+        .and_then(|mut resp| resp.body_mut().read_to_string())
+        .with_context(|| format!("W&B request to {} failed", url))?;
+    let response: serde_json::Value = serde_json::from_str(&response)
+        .with_context(|| format!("Failed to parse W&B response from {}", url))?;
+    if let Some(errors) = response.get("errors") {
+        bail!("W&B rejected the run: {}", errors);
+    }
+    Ok(run)
+}
+
+/// Environment variables making the W&B client started by `kioku run` log into this run
+pub fn environment(run: &WandbRun, meta: &MetaData) -> Vec<(String, String)> {
+    vec![
+        ("WANDB_ENTITY".to_string(), run.entity.clone()),
+        ("WANDB_PROJECT".to_string(), run.project.clone()),
+        ("WANDB_RUN_ID".to_string(), run.run_id.clone()),
+        ("WANDB_NAME".to_string(), meta.label.clone()),
+        ("WANDB_RESUME".to_string(), "allow".to_string()),
+    ]
+}
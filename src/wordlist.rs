@@ -0,0 +1,621 @@
+use crate::config::Config;
+use crate::error::{TypedContext, WordlistErr};
+use anyhow::bail;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+static WORDLIST: &str = include_str!("../assets/wordlist.txt");
+/// Wordlists bundled for `--lang`, English being the default
+static LANGUAGES: &[(&str, &str)] = &[
+    ("en", WORDLIST),
+    ("ja", include_str!("../assets/wordlist.ja.txt")),
+];
+static PROFANITY: &str = include_str!("../assets/profanity.txt");
+
+/// The upstream copy of the bundled wordlist for this release
+static DEFAULT_WORDLIST_URL: &str = concat!(
+    "https://raw.githubusercontent.com/CasBex/kioku-cli/v",
+    env!("CARGO_PKG_VERSION"),
+    "/assets/wordlist.txt"
+);
+
+/// The upstream wordlist for `lang`, which need not be bundled with this release
+fn language_url(lang: &str) -> String {
+    match lang {
+        "en" => DEFAULT_WORDLIST_URL.to_string(),
+        _ => DEFAULT_WORDLIST_URL.replace("wordlist.txt", &format!("wordlist.{}.txt", lang)),
+    }
+}
+
+/// A wordlist entry, sampled proportionally to its weight
+#[derive(Clone)]
+pub struct Word {
+    pub text: String,
+    pub weight: f64,
+}
+
+impl std::fmt::Display for Word {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weight == 1.0 {
+            write!(f, "{}", self.text)
+        } else {
+            write!(f, "{} {}", self.text, self.weight)
+        }
+    }
+}
+
+/// Parse a wordlist line of the form `word` or `word weight`
+fn parse_word(line: &str) -> Option<Word> {
+    let mut fields = line.split_whitespace();
+    let text = fields.next()?;
+    let weight = match fields.next() {
+        Some(weight) => weight
+            .parse::<f64>()
+            .ok()
+            .filter(|w| w.is_finite() && *w > 0.0)?,
+        None => 1.0,
+    };
+    let text: String = text.trim_start_matches('\u{feff}').nfc().collect();
+    // Each grapheme has to start with a letter, which admits letters with combining marks
+    if fields.next().is_some()
+        || text.is_empty()
+        || !text
+            .graphemes(true)
+            .all(|g| g.chars().next().is_some_and(char::is_alphabetic))
+    {
+        return None;
+    }
+    Some(Word { text, weight })
+}
+
+fn wordlist_filter_map(line: &str, dowarn: &mut bool) -> Option<Word> {
+    let word = parse_word(line);
+    if word.is_none() && *dowarn {
+        log::warn!("Wordlist contains invalid words, discarding");
+        *dowarn = false;
+    }
+    word
+}
+
+pub fn parse_lines(reader: impl BufRead) -> Vec<Word> {
+    let mut dowarn = true;
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter(|x| !x.trim().is_empty())
+        .filter_map(|x| wordlist_filter_map(x.as_str(), &mut dowarn))
+        .collect()
+}
+
+pub fn parse_wordlist(filename: &Path) -> anyhow::Result<Vec<Word>> {
+    Ok(parse_lines(io::BufReader::new(
+        fs::File::open(filename).map_err(|source| WordlistErr::Unreadable {
+            path: filename.to_string_lossy().into_owned(),
+            source,
+        })?,
+    )))
+}
+
+/// Read a wordlist piped to stdin, as `--words -`
+pub fn parse_stdin() -> Vec<Word> {
+    parse_lines(io::stdin().lock())
+}
+
+/// Name of the installed wordlist that replaces the bundled one when present
+pub static DEFAULT_NAME: &str = "default";
+
+/// Name under which the default wordlist for `lang` is installed, `default` for English
+pub fn default_name(lang: Option<&str>) -> anyhow::Result<String> {
+    match lang {
+        None | Some("en") => Ok(DEFAULT_NAME.to_string()),
+        Some(lang) => {
+            if lang.is_empty() || !lang.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(WordlistErr::InvalidLanguage(lang.to_string()).into());
+            }
+            Ok(format!("{}-{}", DEFAULT_NAME, lang))
+        }
+    }
+}
+
+/// The wordlist used when `--words` is not given, in language `lang` if given.
+/// An installed wordlist named `default` (`default-<lang>` for other languages) takes precedence
+/// over the bundled one, so the tool keeps working offline while still allowing the default to be
+/// customized.
+pub fn ensure_wordlist(lang: Option<&str>) -> anyhow::Result<Vec<Word>> {
+    let name = default_name(lang)?;
+    if is_installed(&name) {
+        return parse_wordlist(&installed_path(&name)?);
+    }
+    let lang = lang.unwrap_or("en");
+    let Some((_, words)) = LANGUAGES.iter().find(|(l, _)| *l == lang) else {
+        return Err(WordlistErr::NoLanguage(lang.to_string()).into());
+    };
+    Ok(words
+        .lines()
+        .filter_map(|x| wordlist_filter_map(x, &mut false))
+        .collect())
+}
+
+/// Words rejected by the built-in profanity filter
+pub fn profanity() -> impl Iterator<Item = &'static str> {
+    PROFANITY.split_whitespace()
+}
+
+/// Remove excluded words from a wordlist, ignoring case
+pub fn exclude<'a>(words: &mut Vec<Word>, excluded: impl IntoIterator<Item = &'a str>) {
+    let excluded: HashSet<String> = excluded
+        .into_iter()
+        .map(|word| word.trim().nfc().collect::<String>().to_lowercase())
+        .collect();
+    words.retain(|word| !excluded.contains(&word.text.to_lowercase()));
+}
+
+/// Summary of how well a wordlist spreads generated names
+pub struct Stats {
+    pub words: usize,
+    pub duplicates: usize,
+    pub average_length: f64,
+    /// Number of distinct names of the requested length
+    pub namespace: f64,
+    /// Probability that two independently drawn names are equal
+    pub pair_collision: f64,
+}
+
+impl Stats {
+    /// Probability of at least one collision among `draws` names (birthday bound)
+    pub fn collision_probability(&self, draws: u64) -> f64 {
+        let pairs = draws as f64 * draws.saturating_sub(1) as f64 / 2.0;
+        -(-pairs * self.pair_collision).exp_m1()
+    }
+
+    /// Number of draws after which a collision becomes more likely than not
+    pub fn draws_for_even_odds(&self) -> f64 {
+        (2.0 * std::f64::consts::LN_2 / self.pair_collision).sqrt()
+    }
+}
+
+/// Statistics of the wordlist `source` for names of `length` words
+pub fn stats(source: &str, words: &[Word], length: usize) -> anyhow::Result<Stats> {
+    let mut weights: HashMap<&str, f64> = HashMap::new();
+    for word in words {
+        *weights.entry(word.text.as_str()).or_default() += word.weight;
+    }
+    let total: f64 = weights.values().sum();
+    if weights.is_empty() || total <= 0.0 {
+        return Err(WordlistErr::NoValidWords(source.to_string()).into());
+    }
+    let length = i32::try_from(length).unwrap_or(i32::MAX);
+    Ok(Stats {
+        words: words.len(),
+        duplicates: words.len() - weights.len(),
+        average_length: words
+            .iter()
+            .map(|word| word.text.graphemes(true).count())
+            .sum::<usize>() as f64
+            / words.len().max(1) as f64,
+        namespace: (weights.len() as f64).powi(length),
+        pair_collision: weights
+            .values()
+            .map(|w| (w / total).powi(2))
+            .sum::<f64>()
+            .powi(length),
+    })
+}
+
+/// Directory holding the wordlists installed with `kioku wordlist add`
+pub fn wordlist_dir() -> anyhow::Result<PathBuf> {
+    Ok(crate::config::data_dir()?.join("wordlists"))
+}
+
+fn installed_path(name: &str) -> anyhow::Result<PathBuf> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
+    {
+        return Err(WordlistErr::InvalidName(name.to_string()).into());
+    }
+    Ok(wordlist_dir()?.join(format!("{}.txt", name)))
+}
+
+/// Resolve the argument of `--words`, preferring an installed wordlist of that name over a path
+pub fn resolve(words: &str) -> PathBuf {
+    installed_path(words)
+        .ok()
+        .filter(|p| p.is_file())
+        .unwrap_or_else(|| PathBuf::from(words))
+}
+
+pub fn is_installed(name: &str) -> bool {
+    installed_path(name).is_ok_and(|p| p.is_file())
+}
+
+/// Where an installed wordlist was downloaded from, kept next to it for `kioku wordlist update`
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Origin {
+    url: String,
+    /// Checksum the wordlist was pinned to when it was added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    allow_http: bool,
+}
+
+fn origin_path(name: &str) -> anyhow::Result<PathBuf> {
+    Ok(installed_path(name)?.with_extension("origin.json"))
+}
+
+fn read_origin(name: &str) -> anyhow::Result<Option<Origin>> {
+    let path = origin_path(name)?;
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some(serde_json::from_str(&contents).with_typed_context(
+            || format!("Invalid wordlist origin {}", path.to_string_lossy()),
+        )?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e)
+            .with_typed_context(|| format!("Failed to read {}", path.to_string_lossy()))
+            .map_err(Into::into),
+    }
+}
+
+/// HTTP client for downloads, using the configured proxy or the one in `HTTPS_PROXY`,
+/// `HTTP_PROXY` or `ALL_PROXY` unless the host is listed in `NO_PROXY`
+fn agent(config: &Config) -> anyhow::Result<ureq::Agent> {
+    let proxy = match &config.proxy {
+        Some(proxy) => Some(
+            ureq::Proxy::new(proxy).with_typed_context(|| format!("Invalid proxy {}", proxy))?,
+        ),
+        None => ureq::Proxy::try_from_env(),
+    };
+    if let Some(proxy) = &proxy {
+        log::debug!("Using proxy {}:{}", proxy.host(), proxy.port());
+    }
+    Ok(ureq::Agent::config_builder()
+        .proxy(proxy)
+        // Credentials are kept across redirects within a host, not sent on to mirrors or CDNs
+        .redirect_auth_headers(ureq::config::RedirectAuthHeaders::SameHost)
+        .build()
+        .into())
+}
+
+/// Authorization header for wordlist downloads, a bearer token or basic credentials
+fn authorization(config: &Config) -> Option<String> {
+    if let Some(token) = env::var("KIOKU_WORDLIST_TOKEN")
+        .ok()
+        .or_else(|| config.wordlist_token.clone())
+    {
+        return Some(format!("Bearer {}", token));
+    }
+    let user = config.wordlist_user.as_ref()?;
+    let password = env::var("KIOKU_WORDLIST_PASSWORD")
+        .ok()
+        .or_else(|| config.wordlist_password.clone())
+        .unwrap_or_default();
+    Some(format!(
+        "Basic {}",
+        STANDARD.encode(format!("{}:{}", user, password))
+    ))
+}
+
+enum Download {
+    NotModified,
+    Fetched {
+        bytes: Vec<u8>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+/// GET a wordlist, conditionally on it having changed since `origin` was recorded
+fn download(
+    url: &str,
+    allow_http: bool,
+    origin: Option<&Origin>,
+    config: &Config,
+) -> anyhow::Result<Download> {
+    if !url.starts_with("https://") && !allow_http {
+        bail!(
+            "Refusing to download wordlist over an insecure connection from {}, pass --allow-http to override",
+            url
+        );
+    }
+    let mut request = agent(config)?.get(url);
+    if let Some(authorization) = authorization(config) {
+        request = request.header("Authorization", authorization);
+    }
+    if let Some(etag) = origin.and_then(|origin| origin.etag.as_ref()) {
+        request = request.header("If-None-Match", etag);
+    }
+    if let Some(modified) = origin.and_then(|origin| origin.last_modified.as_ref()) {
+        request = request.header("If-Modified-Since", modified);
+    }
+    let mut response = request
+        .call()
+        .with_typed_context(|| format!("Failed to download wordlist from {}", url))?;
+    if response.status() == 304 {
+        return Ok(Download::NotModified);
+    }
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let (etag, last_modified) = (header("etag"), header("last-modified"));
+    let bytes = response
+        .body_mut()
+        .read_to_vec()
+        .with_typed_context(|| format!("Failed to download wordlist from {}", url))?;
+    Ok(Download::Fetched {
+        bytes,
+        etag,
+        last_modified,
+    })
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn verify_checksum(source: &str, bytes: &[u8], expected: &str) -> anyhow::Result<()> {
+    let actual = sha256_hex(bytes);
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(WordlistErr::Checksum {
+            wordlist: source.to_string(),
+            expected: expected.trim().to_string(),
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Install a wordlist under `name`.
+/// Without a source the default wordlist is downloaded from `KIOKU_WORDLIST_URL` or the
+/// configured `wordlist_url`, falling back to the upstream copy of the bundled wordlist, whose
+/// checksum is known. With `lang` the upstream wordlist of that language is downloaded instead.
+pub fn add(
+    name: &str,
+    source: Option<&str>,
+    lang: Option<&str>,
+    sha256: Option<&str>,
+    allow_http: bool,
+    config: &Config,
+) -> anyhow::Result<()> {
+    let env_sha256 = || env::var("KIOKU_WORDLIST_SHA256").ok();
+    let bundled_sha256 = |lang: &str| {
+        LANGUAGES
+            .iter()
+            .find(|(l, _)| *l == lang)
+            .map(|(_, words)| sha256_hex(words.as_bytes()))
+    };
+    let (source, expected) = match (source, lang) {
+        (Some(source), _) => (source.to_string(), sha256.map(str::to_string)),
+        (None, Some(lang)) => (
+            language_url(lang),
+            sha256.map(str::to_string).or_else(|| bundled_sha256(lang)),
+        ),
+        (None, None) => match env::var("KIOKU_WORDLIST_URL")
+            .ok()
+            .or_else(|| config.wordlist_url.clone())
+        {
+            Some(url) => (
+                url,
+                sha256
+                    .map(str::to_string)
+                    .or_else(env_sha256)
+                    .or_else(|| config.wordlist_sha256.clone()),
+            ),
+            None => (
+                DEFAULT_WORDLIST_URL.to_string(),
+                sha256
+                    .map(str::to_string)
+                    .or_else(env_sha256)
+                    .or_else(|| Some(sha256_hex(WORDLIST.as_bytes()))),
+            ),
+        },
+    };
+    let (bytes, origin) = if source.starts_with("https://") || source.starts_with("http://") {
+        log::info!("Downloading wordlist from {}", source);
+        let Download::Fetched {
+            bytes,
+            etag,
+            last_modified,
+        } = download(&source, allow_http, None, config)?
+        else {
+            bail!(
+                "{} answered an unconditional request with 304 Not Modified",
+                source
+            );
+        };
+        let origin = Origin {
+            url: source.clone(),
+            sha256: expected.clone(),
+            etag,
+            last_modified,
+            allow_http,
+        };
+        (bytes, Some(origin))
+    } else {
+        let bytes = fs::read(&source).map_err(|e| WordlistErr::Unreadable {
+            path: source.clone(),
+            source: e,
+        })?;
+        (bytes, None)
+    };
+    install(name, &source, &bytes, expected.as_deref(), origin.as_ref())
+}
+
+/// Verify and store a wordlist, remembering the origin of downloaded ones
+fn install(
+    name: &str,
+    source: &str,
+    bytes: &[u8],
+    expected: Option<&str>,
+    origin: Option<&Origin>,
+) -> anyhow::Result<()> {
+    let target = installed_path(name)?;
+    if let Some(expected) = expected {
+        verify_checksum(source, bytes, expected)?;
+    }
+    let words = parse_lines(io::Cursor::new(bytes));
+    if words.is_empty() {
+        return Err(WordlistErr::NoValidWords(source.to_string()).into());
+    }
+    let dir = wordlist_dir()?;
+    fs::create_dir_all(&dir)
+        .with_typed_context(|| format!("Failed to create directory {}", dir.to_string_lossy()))?;
+    let contents: String = words.iter().map(|word| format!("{}\n", word)).collect();
+    log::debug!("Installing wordlist {}", target.to_string_lossy());
+    fs::write(&target, contents).with_typed_context(|| {
+        format!("Failed to write wordlist file {}", target.to_string_lossy())
+    })?;
+    let origin_path = origin_path(name)?;
+    match origin {
+        Some(origin) => {
+            let contents = serde_json::to_string_pretty(origin)
+                .typed_context("Failed to serialize the wordlist origin")?;
+            fs::write(&origin_path, contents).with_typed_context(|| {
+                format!("Failed to write {}", origin_path.to_string_lossy())
+            })?;
+            Ok(())
+        }
+        // A wordlist replaced from a file no longer follows its former URL
+        None => match fs::remove_file(&origin_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e)
+                .with_typed_context(|| {
+                    format!("Failed to remove {}", origin_path.to_string_lossy())
+                })
+                .map_err(Into::into),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Outcome of refreshing an installed wordlist
+pub enum Update {
+    Updated,
+    UpToDate,
+    /// Installed from a file, so there is nothing to refresh from
+    NoOrigin,
+}
+
+/// Download the wordlist `name` again if its source changed since it was installed
+pub fn update(name: &str, config: &Config) -> anyhow::Result<Update> {
+    if !is_installed(name) {
+        return Err(WordlistErr::NotInstalled(name.to_string()).into());
+    }
+    let Some(mut origin) = read_origin(name)? else {
+        return Ok(Update::NoOrigin);
+    };
+    log::info!("Checking {} for changes", origin.url);
+    match download(&origin.url, origin.allow_http, Some(&origin), config)? {
+        Download::NotModified => Ok(Update::UpToDate),
+        Download::Fetched {
+            bytes,
+            etag,
+            last_modified,
+        } => {
+            let unchanged = fs::read(installed_path(name)?).is_ok_and(|installed| {
+                let words = parse_lines(io::Cursor::new(&bytes));
+                let contents: String = words.iter().map(|word| format!("{}\n", word)).collect();
+                installed == contents.as_bytes()
+            });
+            origin.etag = etag;
+            origin.last_modified = last_modified;
+            let url = origin.url.clone();
+            install(
+                name,
+                &url,
+                &bytes,
+                origin.sha256.clone().as_deref(),
+                Some(&origin),
+            )?;
+            Ok(if unchanged {
+                Update::UpToDate
+            } else {
+                Update::Updated
+            })
+        }
+    }
+}
+
+pub fn list() -> anyhow::Result<Vec<String>> {
+    let dir = wordlist_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(e)
+                .with_typed_context(|| {
+                    format!("Failed to read directory {}", dir.to_string_lossy())
+                })
+                .map_err(Into::into);
+        }
+    };
+    let mut names: Vec<String> = entries
+        .map_while(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "txt") {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            } else {
+                None
+            }
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+pub fn remove(name: &str) -> anyhow::Result<()> {
+    let target = installed_path(name)?;
+    if !target.is_file() {
+        return Err(WordlistErr::NotInstalled(name.to_string()).into());
+    }
+    fs::remove_file(&target).with_typed_context(|| {
+        format!(
+            "Failed to remove wordlist file {}",
+            target.to_string_lossy()
+        )
+    })?;
+    let _ = fs::remove_file(origin_path(name)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_wordlists_have_no_stats() {
+        let error = stats("empty.txt", &[], 3).err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<WordlistErr>(),
+            Some(WordlistErr::NoValidWords(_))
+        ));
+        let words = ["gene", "ruin"].map(|text| Word {
+            text: text.to_string(),
+            weight: 1.0,
+        });
+        let stats = stats("words.txt", &words, 2).unwrap();
+        assert_eq!(stats.namespace, 4.0);
+        assert_eq!(stats.pair_collision, 0.25);
+    }
+}